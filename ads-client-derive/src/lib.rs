@@ -0,0 +1,144 @@
+//! Derive macro for rust-ads-client.
+//! Use it through the `derive` feature of rust-ads-client.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, LitInt};
+
+/// TwinCAT default pack_mode (natural alignment up to 8 bytes)
+const DEFAULT_PACK_MODE: usize = 8;
+
+/// Implements `AdsType` for a struct with named fields.
+/// The members are laid out like TwinCAT does for a PLC struct with the same member order.
+/// Every member type has to implement `AdsType` (nested structs, arrays, strings...).
+///
+/// The TwinCAT pack mode can be set with `#[ads(pack_mode = n)]` (1, 2, 4 or 8). Default is 8.
+/// ```ignore
+/// #[derive(AdsStruct)]
+/// #[ads(pack_mode = 1)]
+/// struct AxisData {
+///     position: f64,
+///     enabled: bool,
+///     name: AdsString<20>,
+/// }
+/// ```
+#[proc_macro_derive(AdsStruct, attributes(ads))]
+pub fn derive_ads_struct(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn expand(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+    let pack_mode = pack_mode(&input)?;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.clone(),
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "AdsStruct can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "AdsStruct can only be derived for structs",
+            ))
+        }
+    };
+
+    let ads_type = quote!(::rust_ads_client::ads_type::AdsType);
+    let align_up = quote!(::rust_ads_client::ads_type::align_up);
+    let pack_align = quote!(::rust_ads_client::ads_type::pack_align);
+
+    for param in input.generics.type_params_mut() {
+        param
+            .bounds
+            .push(parse_quote!(::rust_ads_client::ads_type::AdsType));
+    }
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let names: Vec<_> = fields.iter().map(|f| f.ident.clone().unwrap()).collect();
+    let types: Vec<_> = fields.iter().map(|f| f.ty.clone()).collect();
+    let values: Vec<_> = names
+        .iter()
+        .map(|n| format_ident!("__field_{}", n))
+        .collect();
+
+    Ok(quote! {
+        impl #impl_generics #ads_type for #name #ty_generics #where_clause {
+            const SIZE: usize = {
+                let mut offset = 0usize;
+                #(
+                    offset = #align_up(offset, #pack_align(<#types as #ads_type>::ALIGN, #pack_mode))
+                        + <#types as #ads_type>::SIZE;
+                )*
+                #align_up(offset, <Self as #ads_type>::ALIGN)
+            };
+
+            const ALIGN: usize = {
+                let mut align = 1usize;
+                #(
+                    let member_align = #pack_align(<#types as #ads_type>::ALIGN, #pack_mode);
+                    if member_align > align {
+                        align = member_align;
+                    }
+                )*
+                align
+            };
+
+            fn decode(__data: &[u8]) -> ::rust_ads_client::client::ClientResult<Self> {
+                ::rust_ads_client::ads_type::check_len(__data, <Self as #ads_type>::SIZE)?;
+                let mut __offset = 0usize;
+                #(
+                    __offset = #align_up(__offset, #pack_align(<#types as #ads_type>::ALIGN, #pack_mode));
+                    let #values = <#types as #ads_type>::decode(&__data[__offset..])?;
+                    __offset += <#types as #ads_type>::SIZE;
+                )*
+                let _ = __offset;
+                Ok(#name { #(#names: #values),* })
+            }
+
+            fn encode(&self, __buf: &mut [u8]) -> ::rust_ads_client::client::ClientResult<()> {
+                ::rust_ads_client::ads_type::check_len(__buf, <Self as #ads_type>::SIZE)?;
+                __buf[..<Self as #ads_type>::SIZE].fill(0);
+                let mut __offset = 0usize;
+                #(
+                    __offset = #align_up(__offset, #pack_align(<#types as #ads_type>::ALIGN, #pack_mode));
+                    #ads_type::encode(
+                        &self.#names,
+                        &mut __buf[__offset..__offset + <#types as #ads_type>::SIZE],
+                    )?;
+                    __offset += <#types as #ads_type>::SIZE;
+                )*
+                let _ = __offset;
+                Ok(())
+            }
+        }
+    })
+}
+
+/// Read #[ads(pack_mode = n)]
+fn pack_mode(input: &DeriveInput) -> syn::Result<usize> {
+    let mut pack_mode = DEFAULT_PACK_MODE;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("ads")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("pack_mode") {
+                let value: LitInt = meta.value()?.parse()?;
+                pack_mode = match value.base10_parse::<usize>()? {
+                    0 | 1 => 1,
+                    n @ (2 | 4 | 8) => n,
+                    _ => return Err(meta.error("pack_mode has to be 1, 2, 4 or 8")),
+                };
+                Ok(())
+            } else {
+                Err(meta.error("unsupported ads attribute"))
+            }
+        })?;
+    }
+    Ok(pack_mode)
+}
@@ -0,0 +1,47 @@
+#![allow(unused_imports)]
+//Run with: cargo run --example async_example --features tokio
+use rust_ads_client::async_client::AsyncClient;
+use ads_proto::proto::{
+    ads_transition_mode::AdsTransMode,
+    ams_address::{AmsAddress, AmsNetId},
+};
+use std::net::Ipv4Addr;
+
+#[tokio::main]
+async fn main() {
+    //Create client. If route = None then targed is local machine
+    let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
+    //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
+    //let client = AsyncClient::new(ams_address, Some(ipv4));
+    let client = AsyncClient::new(ams_address, None);
+
+    //Connect client
+    client.connect().await.expect("Failed to connect!");
+
+    //Subscribe to get notifications when "Main.counter" changes
+    let mut notifications = client
+        .add_device_notification("Main.counter", 2, AdsTransMode::OnChange, 1, 1)
+        .await
+        .expect("Failed to add notification!");
+    let task = tokio::spawn(async move {
+        for _ in 0..10 {
+            match notifications.recv().await {
+                Some(Ok((sample, time_stamp))) => println!("{} -> {:?}", time_stamp, sample),
+                Some(Err(e)) => println!("Notification error: {:?}", e),
+                None => break,
+            }
+        }
+    });
+
+    //Read while the notifications are received
+    let var_names = [("Main.counter", 2), ("Main.mi_uint", 2)];
+    for _ in 0..10 {
+        match client.sumup_read_by_name(&var_names).await {
+            Ok(r) => println!("{:?}", r),
+            Err(e) => println!("Error: {:?}", e),
+        }
+    }
+
+    //The notification is deleted on the host when the stream is dropped at the end of the task
+    let _ = task.await;
+}
@@ -0,0 +1,40 @@
+#![allow(unused_imports)]
+use rust_ads_client::client::Client;
+use rust_ads_client::notification::NotificationSettings;
+use ads_proto::proto::ams_address::{AmsAddress, AmsNetId};
+use std::net::Ipv4Addr;
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    //Create client. If route = None then targed is local machine
+    let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
+    //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
+
+    //Connect client
+    client.connect().expect("Failed to connect!");
+
+    //The callback runs on the dispatcher thread of the client. No polling thread needed.
+    //The notification is active as long as the token is kept. Several callbacks can watch the same var.
+    let var = "Main.counter";
+    let token = client
+        .add_device_notification_with_callback(
+            var,
+            2,
+            NotificationSettings::default(),
+            |sample| match sample {
+                Ok((sample, timestamp)) => println!("{}: {:?}", timestamp, sample.data),
+                Err(e) => println!("Error: {}", e),
+            },
+        )
+        .unwrap();
+
+    println!("Receive data...\n");
+    thread::sleep(Duration::from_secs(5));
+
+    //Delete the notification (also done by dropping the token). The callback is dropped.
+    let response = token.unsubscribe();
+    println!("{:?}", response);
+}
@@ -0,0 +1,48 @@
+#![allow(unused_imports)]
+use rust_ads_client::client::Client;
+use rust_ads_client::connection_state::{ConnectionEvent, ConnectionState};
+use rust_ads_client::reconnect::ReconnectPolicy;
+use ads_proto::proto::ams_address::{AmsAddress, AmsNetId};
+use std::net::Ipv4Addr;
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    //Create client. If route = None then targed is local machine
+    let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
+    //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
+    client.set_reconnect_policy(Some(ReconnectPolicy::default()));
+
+    //Subscribe before connecting to see all state changes
+    let rx = client.subscribe_connection_events();
+    let handle = thread::spawn(move || {
+        for event in rx {
+            match event {
+                ConnectionEvent::StateChanged(ConnectionState::Connected) => println!("Online"),
+                ConnectionEvent::StateChanged(state) => println!("Offline ({:?})", state),
+                ConnectionEvent::AdsStateChanged { ads_state, .. } => {
+                    println!("PLC state: {:?}", ads_state)
+                }
+                ConnectionEvent::SymbolVersionChanged(version) => {
+                    println!("New PLC program (symbol version {})", version)
+                }
+                ConnectionEvent::RestoreFailed(error) => {
+                    println!("Handles not restored after a new PLC program: {}", error)
+                }
+            }
+        }
+    });
+
+    //Connect client
+    client.connect().expect("Failed to connect!");
+
+    //Poll the PLC state. Changes (Run -> Stop...) are reported as AdsStateChanged
+    for _ in 0..30 {
+        let _ = client.read_state();
+        thread::sleep(Duration::from_secs(1));
+    }
+    drop(client);
+    let _ = handle.join();
+}
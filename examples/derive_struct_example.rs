@@ -0,0 +1,47 @@
+#![allow(unused_imports)]
+//Run with: cargo run --example derive_struct_example --features derive
+use rust_ads_client::ads_type::{AdsString, AdsStruct};
+use rust_ads_client::client::Client;
+use ads_proto::proto::ams_address::{AmsAddress, AmsNetId};
+use std::net::Ipv4Addr;
+
+//PLC struct ST_Position
+#[derive(Debug, AdsStruct)]
+struct Position {
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+//PLC struct ST_Axis with {attribute 'pack_mode' := '1'}
+#[derive(Debug, AdsStruct)]
+#[ads(pack_mode = 1)]
+struct AxisData {
+    enabled: bool,
+    velocity: f32,
+    position: Position,
+    errors: [u16; 4],
+    name: AdsString<20>,
+}
+
+fn main() {
+    //Create client. If route = None then targed is local machine
+    let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
+    //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
+    //Connect client
+    client.connect().expect("Failed to connect!");
+
+    //Read the whole struct
+    let mut axis: AxisData = client
+        .read_value("Main.stAxis")
+        .expect("Failed to read value!");
+    println!("{:#?}", axis);
+
+    //Modify and write it back
+    axis.enabled = !axis.enabled;
+    client
+        .write_value("Main.stAxis", &axis)
+        .expect("Failed to write value!");
+}
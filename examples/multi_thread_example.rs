@@ -0,0 +1,42 @@
+#![allow(unused_imports)]
+use rust_ads_client::client::Client;
+use ads_proto::{
+    error::AdsError,
+    proto::ams_address::{AmsAddress, AmsNetId},
+};
+use std::net::Ipv4Addr;
+use std::thread;
+
+fn main() {
+    //Create client. If route = None then targed is local machine
+    let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
+    //let ipv4 = Ipv4Addr::new(127, 0, 0, 1);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
+    //Connect client
+    client.connect().expect("Failed to connect!");
+
+    //Every thread gets a clone of the client. All clones share the same connection.
+    let vars = ["Main.counter", "Main.mi_uint", "Main.mi_int"];
+    let mut workers = Vec::new();
+    for var in vars {
+        let client = client.clone();
+        workers.push(thread::spawn(move || {
+            for _ in 0..10 {
+                match client.read_value::<u16>(var) {
+                    Ok(v) => println!("{} = {}", var, v),
+                    Err(e) => println!("Failed to read {}: {:?}", var, e),
+                }
+            }
+        }));
+    }
+
+    for worker in workers {
+        worker.join().expect("Worker thread panicked");
+    }
+
+    //Release handles if not needed anymore
+    for var in vars {
+        let _ = client.release_handle(var);
+    }
+}
@@ -1,17 +1,22 @@
 #![allow(unused_imports)]
 use rust_ads_client::client::Client;
+use rust_ads_client::notification::NotificationSettings;
+use rust_ads_client::reconnect::ReconnectPolicy;
 use ads_proto::proto::{
     ads_transition_mode::AdsTransMode,
     ams_address::{AmsAddress, AmsNetId},
 };
 use std::net::Ipv4Addr;
+use std::time::Duration;
 
 fn main() {
     //Create client. If route = None then targed is local machine
     let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
     //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
-    //let mut client = Client::new(ams_address, Some(ipv4));
-    let mut client = Client::new(ams_address, None);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
+    //Reconnect automatically after a PLC restart. The receiver keeps receiving samples.
+    client.set_reconnect_policy(Some(ReconnectPolicy::default()));
 
     //Connect client
     client.connect().expect("Failed to connect!");
@@ -20,16 +25,21 @@ fn main() {
     let var = "Main.counter";
     let len = 2;
 
-    //Subscribe to get notifications when "Main.counter" changes
-    let rx = client
-        .add_device_notification(var, len, AdsTransMode::OnChange, 1, 1)
+    //Subscribe to get notifications when "Main.counter" changes.
+    //The notification is deleted on the PLC when the subscription is dropped.
+    //Check the value every 10ms and send a change after 100ms at the latest
+    let settings = NotificationSettings::on_change()
+        .cycle_time(Duration::from_millis(10))
+        .max_delay(Duration::from_millis(100));
+    let subscription = client
+        .add_device_notification_with_settings(var, len, settings)
         .unwrap();
 
-    //Poll the mpsc receiver for new values
+    //Poll the subscription for new values
     println!("Receive data...\n");
     let mut list = Vec::new();
     for _ in 1..10 {
-        let result = rx.recv();
+        let result = subscription.recv();
         if let Ok(r) = result.unwrap() {
             list.push(r.0);
         }        
@@ -42,6 +52,6 @@ fn main() {
 
     //Unsubscribe notifications
     println!("\nDelete the notification");
-    let response = client.delete_device_notification(var);
+    let response = subscription.unsubscribe();
     println!("{:?}", response);
 }
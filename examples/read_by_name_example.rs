@@ -1,5 +1,6 @@
 #![allow(unused_imports)]
 use rust_ads_client::client::Client;
+use rust_ads_client::error::ClientError;
 use ads_proto::{
     error::AdsError,
     proto::ams_address::{AmsAddress, AmsNetId},
@@ -10,8 +11,8 @@ fn main() {
     //Create client. If route = None then targed is local machine
     let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
     //let ipv4 = Ipv4Addr::new(127, 0, 0, 1);
-    //let mut client = Client::new(ams_address, Some(ipv4));
-    let mut client = Client::new(ams_address, None);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
     //Connect client
     client.connect().expect("Failed to connect!");
 
@@ -27,19 +28,16 @@ fn main() {
             Ok(r) => {
                 println!("{:?}", r);
             }
-            Err(e) => {
-                if e.is::<AdsError>() {
-                    if let Some(e) = e.downcast_ref::<AdsError>() {
-                        println!("Ads Error{:?}", e);
-                        if client.connect().is_ok() {
-                            println!("Reconnected...");
-                        } else {
-                            println!("Reconnecting failed...");
-                        }
-                    }
+            Err(ClientError::NotConnected) => {
+                println!("Connection lost");
+                if client.connect().is_ok() {
+                    println!("Reconnected...");
+                } else {
+                    println!("Reconnecting failed...");
                 }
-                println!("Other Error{:?}", e);
             }
+            Err(ClientError::Ads(e)) => println!("Ads Error{:?}", e),
+            Err(e) => println!("Other Error: {:?}", e),
         }
     }
 
@@ -7,8 +7,8 @@ fn main() {
     //Create client. If route = None then targed is local machine
     let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
     //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
-    //let mut client = Client::new(ams_address, Some(ipv4));
-    let mut client = Client::new(ams_address, None);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
     //Connect client
     client.connect().expect("Failed to connect!");
 
@@ -0,0 +1,29 @@
+#![allow(unused_imports)]
+use rust_ads_client::client::Client;
+use rust_ads_client::plc_value::PlcValue;
+use ads_proto::proto::ams_address::{AmsAddress, AmsNetId};
+use std::net::Ipv4Addr;
+
+fn main() {
+    //Create client. If route = None then targed is local machine
+    let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
+    //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
+    //Connect client
+    client.connect().expect("Failed to connect!");
+
+    //Read any variable without knowing its type at compile time.
+    //Symbols and data types are uploaded with the first call.
+    for var in ["Main.counter", "Main.stAxis", "Main.stAxis.position"] {
+        match client.read_dynamic(var) {
+            Ok(value) => println!("{} -> {:?}", var, value),
+            Err(e) => println!("{} -> Error: {:?}", var, e),
+        }
+    }
+
+    //Write a value
+    client
+        .write_dynamic("Main.counter", &PlcValue::Int(42))
+        .expect("Failed to write value!");
+}
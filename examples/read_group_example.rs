@@ -0,0 +1,32 @@
+#![allow(unused_imports)]
+use rust_ads_client::client::Client;
+use ads_proto::proto::ams_address::{AmsAddress, AmsNetId};
+use std::net::Ipv4Addr;
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    //Create client. If route = None then targed is local machine
+    let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
+    //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
+    //Connect client
+    client.connect().expect("Failed to connect!");
+
+    //Request the handles and build the sumup request once
+    let mut group = client
+        .prepare_read_group(&[("Main.counter", 2), ("Main.mi_uint", 2), ("Main.mb_bool", 1)])
+        .expect("Failed to prepare the read group");
+
+    //Read the group cyclically. The values are stored in the group.
+    for _ in 0..100 {
+        if let Err(e) = client.read_group(&mut group) {
+            println!("Read failed: {:?}", e);
+            continue;
+        }
+        let counter = group.decode::<u16>(0);
+        println!("counter: {:?}, all: {:?}", counter, group.iter().collect::<Vec<_>>());
+        thread::sleep(Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,43 @@
+#![allow(unused_imports)]
+use rust_ads_client::ads_type::{AdsString, Time};
+use rust_ads_client::client::Client;
+use ads_proto::proto::ams_address::{AmsAddress, AmsNetId};
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+fn main() {
+    //Create client. If route = None then targed is local machine
+    let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
+    //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
+    //Connect client
+    client.connect().expect("Failed to connect!");
+
+    //Write and read an INT. The length is taken from the type.
+    client
+        .write_value::<i16>("Main.counter", &1111)
+        .expect("Failed to write value!");
+    let counter: i16 = client
+        .read_value("Main.counter")
+        .expect("Failed to read value!");
+    println!("Main.counter  -> {}", counter);
+
+    //STRING(80)
+    let text: AdsString<80> = client
+        .read_value("Main.ms_string")
+        .expect("Failed to read value!");
+    println!("Main.ms_string -> {}", text.0);
+
+    //TIME
+    let time: Time = client
+        .read_value("Main.mt_time")
+        .expect("Failed to read value!");
+    println!("Main.mt_time  -> {:?}", Duration::from(time));
+
+    //ARRAY [0..9] OF BOOL
+    let flags: [bool; 10] = client
+        .read_value("Main.ma_bool")
+        .expect("Failed to read value!");
+    println!("Main.ma_bool  -> {:?}", flags);
+}
@@ -0,0 +1,34 @@
+#![allow(unused_imports)]
+use rust_ads_client::client::Client;
+use rust_ads_client::notification::NotificationSettings;
+use ads_proto::proto::ams_address::{AmsAddress, AmsNetId};
+use std::net::Ipv4Addr;
+
+fn main() {
+    //Create client. If route = None then targed is local machine
+    let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
+    //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
+
+    //Connect client
+    client.connect().expect("Failed to connect!");
+
+    //Subscribe to "Main.counter" (UINT). The length is taken from the type.
+    let var = "Main.counter";
+    let subscription = client
+        .subscribe::<u16>(var, NotificationSettings::default())
+        .unwrap();
+
+    println!("Receive data...\n");
+    for notification in subscription.iter().take(10) {
+        match notification {
+            Ok(n) => println!("{:?}: {}", n.timestamp, n.value),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+
+    //Unsubscribe notifications (also done by dropping the subscription)
+    let response = subscription.unsubscribe();
+    println!("{:?}", response);
+}
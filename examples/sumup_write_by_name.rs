@@ -1,55 +1,57 @@
 #![allow(unused_imports)]
 use rust_ads_client::client::Client;
+use rust_ads_client::error::ClientError;
 use ads_proto::{
     error::AdsError,
-    proto::{
-        ams_address::{AmsAddress, AmsNetId},
-        response::WriteResponse,
-    },
+    proto::ams_address::{AmsAddress, AmsNetId},
 };
-use std::{collections::HashMap, net::Ipv4Addr};
+use std::net::Ipv4Addr;
 
 fn main() {
     //Create client. If route = None then targed is local machine
     let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
     //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
-    //let mut client = Client::new(ams_address, Some(ipv4));
-    let mut client = Client::new(ams_address, None);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
     //Connect client
     client.connect().expect("Failed to connect!");
 
     //var name and length
-    let mut var_names: HashMap<String, Vec<u8>> = HashMap::new();
-    var_names.insert("Main.counter".to_string(), vec![0, 0]);
-    var_names.insert("Main.mi_uint".to_string(), vec![0, 0]);
-    var_names.insert("Main.mb_bool".to_string(), vec![1]);
+    let var_names = [
+        ("Main.counter", vec![0, 0]),
+        ("Main.mi_uint", vec![0, 0]),
+        ("Main.mb_bool", vec![1]),
+    ];
 
     //write data vor all variables in the list with one (tcp) request
     let iterations = 10;
-    let mut results: Vec<HashMap<String, WriteResponse>> = Vec::new();
+    let mut results = Vec::new();
     for _ in 0..iterations {
-        match client.sumup_write_by_name(var_names.clone()) {
+        match client.sumup_write_by_name(&var_names) {
             Ok(r) => {
                 results.push(r);
             }
-            Err(e) => {
-                if e.is::<AdsError>() {
-                    if let Some(e) = e.downcast_ref::<AdsError>() {
-                        println!("Some Ads Error{:?}", e);
-                        if client.connect().is_ok() {
-                            println!("Reconnected...");
-                        } else {
-                            println!("Reconnecting failed...");
-                        }
-                    }
+            Err(ClientError::NotConnected) => {
+                println!("Connection lost");
+                if client.connect().is_ok() {
+                    println!("Reconnected...");
+                } else {
+                    println!("Reconnecting failed...");
                 }
-                println!("Other Error{:?}", e);
             }
+            Err(ClientError::Ads(e)) => println!("Some Ads Error{:?}", e),
+            Err(e) => println!("Other Error: {:?}", e),
         }
     }
 
+    //Results are in the order of var_names
     for r in results {
-        println!("{:?}", r);
+        for (name, result) in r {
+            match result {
+                Ok(()) => println!("{}: written", name),
+                Err(e) => println!("{} failed: {:?}", name, e),
+            }
+        }
     }
 
     //Release handle if not needed anymore
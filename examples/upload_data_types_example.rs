@@ -0,0 +1,53 @@
+#![allow(unused_imports)]
+use rust_ads_client::client::Client;
+use rust_ads_client::data_type::{DataType, DataTypeKind};
+use ads_proto::proto::ams_address::{AmsAddress, AmsNetId};
+use std::net::Ipv4Addr;
+
+fn main() {
+    //Create client. If route = None then targed is local machine
+    let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
+    //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
+
+    //Connect client
+    client.connect().expect("Failed to connect!");
+
+    //Upload symbols and data types
+    let symbols = client.upload_symbols().expect("Failed to upload symbols!");
+    let data_types = client
+        .upload_data_types()
+        .expect("Failed to upload data types!");
+    println!("{} data types uploaded\n", data_types.len());
+
+    //Resolve the type of a struct variable and print the layout
+    let var = "Main.stAxis";
+    let symbol = symbols.get(var).expect("Symbol not found!");
+    let data_type = data_types
+        .resolve(&symbol.type_name)
+        .expect("Failed to resolve type!");
+    print_type(var, &data_type, 0, 0);
+}
+
+fn print_type(name: &str, data_type: &DataType, offset: u32, indent: usize) {
+    println!(
+        "{:indent$}{} : {} (offset: {}, size: {})",
+        "",
+        name,
+        data_type.name,
+        offset,
+        data_type.size,
+        indent = indent
+    );
+    if let DataTypeKind::Struct(members) = &data_type.kind {
+        for member in members {
+            print_type(
+                &member.name,
+                &member.data_type,
+                offset + member.offset,
+                indent + 4,
+            );
+        }
+    }
+}
@@ -0,0 +1,36 @@
+#![allow(unused_imports)]
+use rust_ads_client::client::Client;
+use ads_proto::proto::ams_address::{AmsAddress, AmsNetId};
+use std::net::Ipv4Addr;
+
+fn main() {
+    //Create client. If route = None then targed is local machine
+    let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
+    //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
+
+    //Connect client
+    client.connect().expect("Failed to connect!");
+
+    //Upload all symbols from the PLC
+    let symbols = client.upload_symbols().expect("Failed to upload symbols!");
+    println!("{} symbols uploaded\n", symbols.len());
+    for symbol in &symbols {
+        println!(
+            "{:<40} {:<20} size: {:<6} group: {:#x} offset: {:#x} {}",
+            symbol.name,
+            symbol.type_name,
+            symbol.size,
+            symbol.index_group,
+            symbol.index_offset,
+            symbol.comment
+        );
+    }
+
+    //Lookup a single symbol and read it with the uploaded size
+    if let Some(symbol) = symbols.get("Main.counter") {
+        let response = client.read_by_name(&symbol.name, symbol.size);
+        println!("\n{:?}", response);
+    }
+}
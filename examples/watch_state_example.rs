@@ -0,0 +1,30 @@
+#![allow(unused_imports)]
+use rust_ads_client::client::Client;
+use ads_proto::proto::{
+    ads_state::AdsState,
+    ams_address::{AmsAddress, AmsNetId},
+};
+use std::net::Ipv4Addr;
+
+fn main() {
+    //Create client. If route = None then targed is local machine
+    let ams_address = AmsAddress::new(AmsNetId::new(192, 168, 0, 150, 1, 1), 851);
+    //let ipv4 = Ipv4Addr::new(192, 168, 0, 150);
+    //let client = Client::new(ams_address, Some(ipv4));
+    let client = Client::new(ams_address, None);
+
+    //Connect client
+    client.connect().expect("Failed to connect!");
+
+    //Receive every ADS state transition of the PLC (Run -> Stop -> Config...)
+    //The notification is deleted on the PLC when the watch is dropped
+    let watch = client.watch_state().expect("Failed to watch the state");
+    println!("Switch the PLC to Stop or Config...\n");
+    for state in watch.iter().take(5) {
+        match state {
+            Ok((AdsState::AdsStateRun, _)) => println!("PLC running"),
+            Ok((ads_state, device_state)) => println!("{:?} (device state {})", ads_state, device_state),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use crate::client::ClientResult;
+use crate::error::ClientError;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "derive")]
+pub use ads_client_derive::AdsStruct;
+
+/// Rust representation of a PLC data type.
+/// The byte size is known at compile time so typed reads don't need a length parameter.
+pub trait AdsType: Sized {
+    /// Byte size of the PLC type
+    const SIZE: usize;
+
+    /// Natural alignment of the PLC type. Used to lay out struct members (see pack_mode).
+    const ALIGN: usize = 1;
+
+    /// Decode a value from the PLC representation. `data` must hold at least SIZE bytes.
+    fn decode(data: &[u8]) -> ClientResult<Self>;
+
+    /// Encode the value into the PLC representation. `buf` must hold at least SIZE bytes.
+    fn encode(&self, buf: &mut [u8]) -> ClientResult<()>;
+
+    /// Encode the value into a new buffer
+    fn to_bytes(&self) -> ClientResult<Vec<u8>> {
+        let mut buf = vec![0; Self::SIZE];
+        self.encode(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// Round offset up to the next multiple of align
+pub const fn align_up(offset: usize, align: usize) -> usize {
+    if align <= 1 {
+        offset
+    } else {
+        offset.div_ceil(align) * align
+    }
+}
+
+/// Alignment of a struct member with the given natural alignment under a TwinCAT pack_mode
+pub const fn pack_align(align: usize, pack_mode: usize) -> usize {
+    if align < pack_mode {
+        align
+    } else {
+        pack_mode
+    }
+}
+
+/// Fails if a buffer to decode from or encode into is shorter than the expected PLC type size
+pub fn check_len(data: &[u8], len: usize) -> ClientResult<()> {
+    if data.len() < len {
+        return Err(ClientError::Decode(format!(
+            "Buffer too short for value. Expected {} bytes, got {}",
+            len,
+            data.len()
+        )));
+    }
+    Ok(())
+}
+
+impl AdsType for bool {
+    const SIZE: usize = 1;
+
+    fn decode(data: &[u8]) -> ClientResult<Self> {
+        check_len(data, Self::SIZE)?;
+        Ok(data[0] != 0)
+    }
+
+    fn encode(&self, buf: &mut [u8]) -> ClientResult<()> {
+        check_len(buf, Self::SIZE)?;
+        buf[0] = *self as u8;
+        Ok(())
+    }
+}
+
+macro_rules! impl_ads_type_number {
+    ($($t:ty),*) => {
+        $(
+            impl AdsType for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+                const ALIGN: usize = std::mem::size_of::<$t>();
+
+                fn decode(data: &[u8]) -> ClientResult<Self> {
+                    check_len(data, Self::SIZE)?;
+                    let mut bytes = [0; std::mem::size_of::<$t>()];
+                    bytes.copy_from_slice(&data[..Self::SIZE]);
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+
+                fn encode(&self, buf: &mut [u8]) -> ClientResult<()> {
+                    check_len(buf, Self::SIZE)?;
+                    buf[..Self::SIZE].copy_from_slice(&self.to_le_bytes());
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+// BYTE/USINT, SINT, WORD/UINT, INT, DWORD/UDINT, DINT, LWORD/ULINT, LINT, REAL, LREAL
+impl_ads_type_number!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+/// Arrays of any PLC type (ARRAY [0..N-1] OF T)
+impl<T: AdsType, const N: usize> AdsType for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+    const ALIGN: usize = T::ALIGN;
+
+    fn decode(data: &[u8]) -> ClientResult<Self> {
+        check_len(data, Self::SIZE)?;
+        let mut values = Vec::with_capacity(N);
+        for chunk in data[..Self::SIZE].chunks_exact(T::SIZE.max(1)).take(N) {
+            values.push(T::decode(chunk)?);
+        }
+        values
+            .try_into()
+            .map_err(|_| ClientError::Decode(format!("Failed to decode array of {} elements", N)))
+    }
+
+    fn encode(&self, buf: &mut [u8]) -> ClientResult<()> {
+        check_len(buf, Self::SIZE)?;
+        for (value, chunk) in self.iter().zip(buf.chunks_exact_mut(T::SIZE.max(1))) {
+            value.encode(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! impl_ads_type_u32_newtype {
+    ($t:ident) => {
+        impl AdsType for $t {
+            const SIZE: usize = 4;
+            const ALIGN: usize = 4;
+
+            fn decode(data: &[u8]) -> ClientResult<Self> {
+                Ok($t(u32::decode(data)?))
+            }
+
+            fn encode(&self, buf: &mut [u8]) -> ClientResult<()> {
+                self.0.encode(buf)
+            }
+        }
+    };
+}
+
+/// PLC TIME. Milliseconds as u32.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Time(pub u32);
+impl_ads_type_u32_newtype!(Time);
+
+impl From<Time> for Duration {
+    fn from(t: Time) -> Self {
+        Duration::from_millis(t.0 as u64)
+    }
+}
+
+impl TryFrom<Duration> for Time {
+    type Error = ClientError;
+
+    /// Fails for durations longer than u32::MAX milliseconds
+    fn try_from(d: Duration) -> Result<Self, Self::Error> {
+        Ok(Time(duration_millis(d)?))
+    }
+}
+
+/// PLC TIME_OF_DAY (TOD). Milliseconds since midnight as u32.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimeOfDay(pub u32);
+impl_ads_type_u32_newtype!(TimeOfDay);
+
+impl From<TimeOfDay> for Duration {
+    fn from(t: TimeOfDay) -> Self {
+        Duration::from_millis(t.0 as u64)
+    }
+}
+
+impl TryFrom<Duration> for TimeOfDay {
+    type Error = ClientError;
+
+    /// Fails for durations longer than u32::MAX milliseconds
+    fn try_from(d: Duration) -> Result<Self, Self::Error> {
+        Ok(TimeOfDay(duration_millis(d)?))
+    }
+}
+
+/// Milliseconds of a TIME or TIME_OF_DAY value
+fn duration_millis(d: Duration) -> ClientResult<u32> {
+    u32::try_from(d.as_millis()).map_err(|_| {
+        ClientError::Decode(format!("Duration of {:?} exceeds u32::MAX milliseconds", d))
+    })
+}
+
+/// PLC DATE. Seconds since 1970-01-01 as u32 (the time part is always 0).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date(pub u32);
+impl_ads_type_u32_newtype!(Date);
+
+impl From<Date> for SystemTime {
+    fn from(d: Date) -> Self {
+        UNIX_EPOCH + Duration::from_secs(d.0 as u64)
+    }
+}
+
+/// PLC DATE_AND_TIME (DT). Seconds since 1970-01-01 as u32.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateAndTime(pub u32);
+impl_ads_type_u32_newtype!(DateAndTime);
+
+impl From<DateAndTime> for SystemTime {
+    fn from(dt: DateAndTime) -> Self {
+        UNIX_EPOCH + Duration::from_secs(dt.0 as u64)
+    }
+}
+
+impl TryFrom<SystemTime> for DateAndTime {
+    type Error = ClientError;
+
+    /// Fails for times before 1970 or after 2106
+    fn try_from(t: SystemTime) -> Result<Self, Self::Error> {
+        let secs = t
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| ClientError::Decode(e.to_string()))?
+            .as_secs();
+        let secs = u32::try_from(secs).map_err(|e| ClientError::Decode(e.to_string()))?;
+        Ok(DateAndTime(secs))
+    }
+}
+
+/// PLC STRING(N). N bytes plus the null terminator.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AdsString<const N: usize>(pub String);
+
+impl<const N: usize> AdsType for AdsString<N> {
+    const SIZE: usize = N + 1;
+
+    fn decode(data: &[u8]) -> ClientResult<Self> {
+        check_len(data, Self::SIZE)?;
+        let data = &data[..Self::SIZE];
+        let end = data.iter().position(|b| *b == 0).unwrap_or(N);
+        Ok(AdsString(String::from_utf8_lossy(&data[..end]).to_string()))
+    }
+
+    fn encode(&self, buf: &mut [u8]) -> ClientResult<()> {
+        check_len(buf, Self::SIZE)?;
+        let bytes = self.0.as_bytes();
+        if bytes.len() > N {
+            return Err(ClientError::Decode(format!(
+                "String with {} bytes does not fit into STRING({})",
+                bytes.len(),
+                N
+            )));
+        }
+        buf[..bytes.len()].copy_from_slice(bytes);
+        buf[bytes.len()..Self::SIZE].fill(0);
+        Ok(())
+    }
+}
+
+impl<const N: usize> From<&str> for AdsString<N> {
+    fn from(s: &str) -> Self {
+        AdsString(s.to_string())
+    }
+}
+
+/// PLC WSTRING(N). N UTF-16 code units plus the null terminator.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AdsWString<const N: usize>(pub String);
+
+impl<const N: usize> AdsType for AdsWString<N> {
+    const SIZE: usize = 2 * (N + 1);
+    const ALIGN: usize = 2;
+
+    fn decode(data: &[u8]) -> ClientResult<Self> {
+        check_len(data, Self::SIZE)?;
+        let units: Vec<u16> = data[..Self::SIZE]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|u| *u != 0)
+            .collect();
+        Ok(AdsWString(String::from_utf16_lossy(&units)))
+    }
+
+    fn encode(&self, buf: &mut [u8]) -> ClientResult<()> {
+        check_len(buf, Self::SIZE)?;
+        let units: Vec<u16> = self.0.encode_utf16().collect();
+        if units.len() > N {
+            return Err(ClientError::Decode(format!(
+                "String with {} UTF-16 units does not fit into WSTRING({})",
+                units.len(),
+                N
+            )));
+        }
+        for (unit, chunk) in units.iter().zip(buf.chunks_exact_mut(2)) {
+            chunk.copy_from_slice(&unit.to_le_bytes());
+        }
+        buf[units.len() * 2..Self::SIZE].fill(0);
+        Ok(())
+    }
+}
+
+impl<const N: usize> From<&str> for AdsWString<N> {
+    fn from(s: &str) -> Self {
+        AdsWString(s.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: AdsType + PartialEq + std::fmt::Debug>(value: T, bytes: &[u8]) {
+        assert_eq!(value.to_bytes().unwrap(), bytes);
+        assert_eq!(T::decode(bytes).unwrap(), value);
+    }
+
+    fn short_buffer<T: AdsType>(value: T) {
+        let mut buf = vec![0; T::SIZE - 1];
+        assert!(matches!(
+            value.encode(&mut buf),
+            Err(ClientError::Decode(_))
+        ));
+        assert!(matches!(T::decode(&buf), Err(ClientError::Decode(_))));
+    }
+
+    #[test]
+    fn primitives_round_trip() {
+        round_trip(true, &[1]);
+        round_trip(false, &[0]);
+        round_trip(0xABu8, &[0xAB]);
+        round_trip(-2i8, &[0xFE]);
+        round_trip(0x1234u16, &[0x34, 0x12]);
+        round_trip(-2i16, &[0xFE, 0xFF]);
+        round_trip(0x1234_5678u32, &[0x78, 0x56, 0x34, 0x12]);
+        round_trip(-2i32, &[0xFE, 0xFF, 0xFF, 0xFF]);
+        round_trip(0x0102_0304_0506_0708u64, &[8, 7, 6, 5, 4, 3, 2, 1]);
+        round_trip(-2i64, &[0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        round_trip(1.5f32, &1.5f32.to_le_bytes());
+        round_trip(-1.5f64, &(-1.5f64).to_le_bytes());
+        round_trip(Time(1000), &[0xE8, 0x03, 0, 0]);
+        round_trip([1u16, 2, 3], &[1, 0, 2, 0, 3, 0]);
+        round_trip(AdsString::<4>::from("ab"), b"ab\0\0\0");
+        round_trip(AdsWString::<2>::from("a"), &[b'a', 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn decode_ignores_trailing_data() {
+        assert_eq!(u16::decode(&[0x34, 0x12, 0xFF]).unwrap(), 0x1234);
+        assert_eq!(AdsString::<4>::decode(b"ab\0cd\0x").unwrap().0, "ab");
+    }
+
+    #[test]
+    fn short_buffers_fail() {
+        short_buffer(true);
+        short_buffer(1u16);
+        short_buffer(1.5f64);
+        short_buffer(Date(0));
+        short_buffer([1u32; 2]);
+        short_buffer(AdsString::<4>::from("ab"));
+        short_buffer(AdsWString::<2>::from("a"));
+    }
+
+    #[test]
+    fn durations_beyond_u32_millis_fail() {
+        assert_eq!(
+            Time::try_from(Duration::from_millis(1500)).unwrap(),
+            Time(1500)
+        );
+        let max = Duration::from_millis(u32::MAX as u64);
+        assert_eq!(TimeOfDay::try_from(max).unwrap(), TimeOfDay(u32::MAX));
+        let too_long = max + Duration::from_millis(1);
+        assert!(matches!(
+            Time::try_from(too_long),
+            Err(ClientError::Decode(_))
+        ));
+        assert!(matches!(
+            TimeOfDay::try_from(too_long),
+            Err(ClientError::Decode(_))
+        ));
+        assert_eq!(Duration::from(Time(1500)), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn string_too_long() {
+        assert!(AdsString::<2>::from("abc").to_bytes().is_err());
+        assert!(AdsWString::<2>::from("abc").to_bytes().is_err());
+    }
+}
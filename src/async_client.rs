@@ -0,0 +1,1099 @@
+use crate::ads_type::AdsType;
+use crate::client::{
+    delete_notification_frames, local_port_address, lock, notification_handle, request_frame,
+    var_handle, ClientResult, ReaderHealth, ReaderState, SumupResult, TimeStamp,
+    ADS_TCP_SERVER_PORT, AMS_PORT_CONNECT_REQUEST, AMS_PORT_CONNECT_RESPONSE_LEN,
+    DEFAULT_REQUEST_TIMEOUT,
+};
+use crate::error::ClientError;
+use crate::reader::{
+    ams_packet_length, extract_notification_stream, extract_response, parse_packet, record_error,
+    MalformedPacket, AMS_TCP_HEADER_SIZE,
+};
+use crate::request_factory::{self, *};
+use crate::sumup::*;
+use ads_proto::error::AdsError;
+use ads_proto::proto::ads_state::AdsState;
+use ads_proto::proto::ads_transition_mode::AdsTransMode;
+use ads_proto::proto::ams_address::{AmsAddress, AmsNetId};
+use ads_proto::proto::ams_header::AmsHeader;
+use ads_proto::proto::command_id::CommandID;
+use ads_proto::proto::request::{
+    ReadDeviceInfoRequest, ReadStateRequest, ReadWriteRequest, Request,
+};
+use ads_proto::proto::response::*;
+use futures_core::Stream;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+type NotificationResult = ClientResult<(AdsNotificationSample, TimeStamp)>;
+
+#[derive(Debug, Default)]
+struct SenderTables {
+    general: HashMap<u32, oneshot::Sender<ClientResult<Response>>>,
+    device_notification: HashMap<u32, mpsc::UnboundedSender<NotificationResult>>,
+}
+
+/// Stream of device notifications returned by AsyncClient::add_device_notification.
+/// The notification is deleted on the host when the stream is dropped.
+#[derive(Debug)]
+pub struct NotificationStream {
+    rx: mpsc::UnboundedReceiver<NotificationResult>,
+    id: u64,
+    client: Weak<AsyncClientInner>,
+}
+
+impl NotificationStream {
+    /// Receive the next notification. Returns None if the notification has been deleted.
+    pub async fn recv(&mut self) -> Option<NotificationResult> {
+        self.rx.recv().await
+    }
+}
+
+impl Stream for NotificationStream {
+    type Item = NotificationResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl Drop for NotificationStream {
+    fn drop(&mut self) {
+        let Some(inner) = self.client.upgrade() else {
+            return;
+        };
+        let Some((_, handle)) = lock(&inner.notifications).remove(&self.id) else {
+            //Already deleted
+            return;
+        };
+        lock(&inner.sender_tables)
+            .device_notification
+            .remove(&handle);
+        //Deleting needs a request. It is sent by a task if the stream is dropped within a runtime.
+        if let Ok(runtime) = Handle::try_current() {
+            let client = AsyncClient { inner };
+            runtime.spawn(async move {
+                let _ = client.delete_notification_handle(handle).await;
+            });
+        }
+    }
+}
+
+/// Async ADS client for tokio.
+/// Same functionality as Client but responses are received by a tokio task instead of a reader thread.
+/// The client is cheap to clone. All clones share one connection, so the client can be shared between tasks.
+#[derive(Debug, Clone)]
+pub struct AsyncClient {
+    inner: Arc<AsyncClientInner>,
+}
+
+#[derive(Debug)]
+struct AsyncClientInner {
+    route: Option<Ipv4Addr>,
+    ams_targed_address: AmsAddress,
+    ams_source_address: Mutex<AmsAddress>,
+    /// Write half of the connection. Cleared by the reader task if the connection is lost.
+    writer: Arc<tokio::sync::Mutex<Option<OwnedWriteHalf>>>,
+    reader_task: Mutex<Option<JoinHandle<()>>>,
+    invoke_id: AtomicU32,
+    timeout: Mutex<Duration>,
+    sender_tables: Arc<Mutex<SenderTables>>,
+    reader_health: Arc<Mutex<ReaderHealth>>,
+    handle_list: Mutex<HashMap<String, u32>>,
+    /// Var name and notification handle of the active notifications by notification id
+    notifications: Mutex<HashMap<u64, (String, u32)>>,
+    notification_id: AtomicU64,
+}
+
+impl Drop for AsyncClientInner {
+    fn drop(&mut self) {
+        if let Some(task) = lock(&self.reader_task).take() {
+            task.abort();
+        }
+        //Free the notification slots of the host. The responses are not awaited.
+        let writer = self
+            .writer
+            .try_lock()
+            .ok()
+            .and_then(|mut writer| writer.take());
+        if let (Some(mut writer), Ok(runtime)) = (writer, Handle::try_current()) {
+            let frames = delete_notification_frames(
+                &self.ams_targed_address,
+                &lock(&self.ams_source_address),
+                &self.invoke_id,
+                lock(&self.notifications)
+                    .drain()
+                    .map(|(_, (_, handle))| handle),
+            );
+            runtime.spawn(async move {
+                let _ = writer.write_all(&frames).await;
+                let _ = writer.shutdown().await;
+            });
+        }
+    }
+}
+
+impl AsyncClientInner {
+    /// Var handles and notifications of a lost connection are invalid on the host.
+    /// The notification streams end after they received the NotConnected error.
+    fn forget_session(&self) {
+        lock(&self.handle_list).clear();
+        lock(&self.notifications).clear();
+        lock(&self.sender_tables).device_notification.clear();
+    }
+}
+
+impl AsyncClient {
+    /// Setup a new client. This will will not yet connect to the targed.
+    /// Call connect() after creation.
+    pub fn new(ams_targed_address: AmsAddress, route: Option<Ipv4Addr>) -> Self {
+        let inner = AsyncClientInner {
+            route,
+            ams_targed_address,
+            ams_source_address: Mutex::new(AmsAddress::new(AmsNetId::from([0, 0, 0, 0, 0, 0]), 0)),
+            writer: Arc::new(tokio::sync::Mutex::new(None)),
+            reader_task: Mutex::new(None),
+            invoke_id: AtomicU32::new(0),
+            timeout: Mutex::new(DEFAULT_REQUEST_TIMEOUT),
+            sender_tables: Arc::new(Mutex::new(SenderTables::default())),
+            reader_health: Arc::new(Mutex::new(ReaderHealth::default())),
+            handle_list: Mutex::new(HashMap::new()),
+            notifications: Mutex::new(HashMap::new()),
+            notification_id: AtomicU64::new(0),
+        };
+        AsyncClient {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Connect to host and start the reader task.
+    /// Fails if host is not reachable.
+    pub async fn connect(&self) -> ClientResult<ReadStateResponse> {
+        {
+            let mut writer = self.inner.writer.lock().await;
+            if writer.is_none() {
+                let route = self.inner.route.unwrap_or(Ipv4Addr::LOCALHOST);
+                let mut stream =
+                    TcpStream::connect(SocketAddr::from((route, ADS_TCP_SERVER_PORT))).await?;
+                stream.set_nodelay(true)?;
+
+                if self.inner.route.is_none() {
+                    //open local port in case of local machine
+                    stream.write_all(&AMS_PORT_CONNECT_REQUEST).await?;
+                    let mut buf = [0; AMS_PORT_CONNECT_RESPONSE_LEN];
+                    stream.read_exact(&mut buf).await?;
+                    *lock(&self.inner.ams_source_address) = local_port_address(&buf)?;
+                } else {
+                    lock(&self.inner.ams_source_address)
+                        .update_from_socket_addr(stream.local_addr()?)?;
+                }
+
+                let (read_half, write_half) = stream.into_split();
+                let mut reader_task = lock(&self.inner.reader_task);
+                if let Some(task) = reader_task.take() {
+                    task.abort();
+                    notify_connection_down(&self.inner.sender_tables);
+                }
+                *lock(&self.inner.reader_health) = ReaderHealth {
+                    state: ReaderState::Running,
+                    ..ReaderHealth::default()
+                };
+                *reader_task = Some(tokio::spawn(run_reader_task(
+                    read_half,
+                    self.inner.sender_tables.clone(),
+                    self.inner.writer.clone(),
+                    self.inner.reader_health.clone(),
+                    Arc::downgrade(&self.inner),
+                )));
+                *writer = Some(write_half);
+            }
+        }
+        //Check if host is responding
+        self.read_state().await
+    }
+
+    /// State, decode error count and last error of the reader task (see Client::reader_health)
+    pub fn reader_health(&self) -> ReaderHealth {
+        lock(&self.inner.reader_health).clone()
+    }
+
+    /// Set the time to wait for a response. Used by request and all methods built on it.
+    /// Default is DEFAULT_REQUEST_TIMEOUT
+    pub fn set_timeout(&self, timeout: Duration) {
+        *lock(&self.inner.timeout) = timeout;
+    }
+
+    /// Time to wait for a response
+    pub fn timeout(&self) -> Duration {
+        *lock(&self.inner.timeout)
+    }
+
+    /// Sends the supplied request
+    /// Waits until the response has been received, an error occures or the timeout (see set_timeout) elapsed
+    /// Fails if no tcp stream is available.
+    pub async fn request(&self, request: Request) -> ClientResult<Response> {
+        self.request_timeout(request, self.timeout()).await
+    }
+
+    /// Sends the supplied request and waits at most `timeout` for the response.
+    /// Fails with ClientError::Timeout if no response has been received in time.
+    pub async fn request_timeout(
+        &self,
+        request: Request,
+        timeout: Duration,
+    ) -> ClientResult<Response> {
+        let pending = self.send_request(request).await?;
+        self.wait_response(pending, timeout).await
+    }
+
+    /// Send all requests before waiting for the first response. All responses have to arrive within the timeout.
+    /// Returns the responses in the order of the requests. Fails with the first failed request,
+    /// the requests still waiting for a response are cancelled then.
+    async fn request_pipelined(&self, requests: Vec<Request>) -> ClientResult<Vec<Response>> {
+        let timeout = self.timeout();
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut pending = Vec::with_capacity(requests.len());
+        for request in requests {
+            pending.push(self.send_request(request).await?);
+        }
+
+        let mut responses = Vec::with_capacity(pending.len());
+        for pending in pending {
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            match self.wait_response(pending, remaining).await {
+                Ok(response) => responses.push(response),
+                Err(ClientError::Timeout { invoke_id, .. }) => {
+                    return Err(ClientError::Timeout { invoke_id, timeout })
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(responses)
+    }
+
+    /// Wait for the response to a sent request
+    async fn wait_response(
+        &self,
+        mut pending: PendingResponse,
+        timeout: Duration,
+    ) -> ClientResult<Response> {
+        let response = match tokio::time::timeout(timeout, &mut pending.rx).await {
+            Ok(response) => response.unwrap_or(Err(ClientError::NotConnected)),
+            Err(_) => {
+                return Err(ClientError::Timeout {
+                    invoke_id: pending.invoke_id,
+                    timeout,
+                })
+            }
+        };
+        self.check_tcp_stream(&response).await;
+        response
+    }
+
+    /// Sends a request and returns imediatly a receiver (oneshot::Receiver) to await the response.
+    /// Fails if no tcp stream is available.
+    pub async fn request_rx(
+        &self,
+        request: Request,
+    ) -> ClientResult<oneshot::Receiver<ClientResult<Response>>> {
+        Ok(self.send_request(request).await?.into_receiver())
+    }
+
+    /// Register a sender for the response at the reader task and write the request
+    /// Returns the receiver of the response
+    async fn send_request(&self, request: Request) -> ClientResult<PendingResponse> {
+        let invoke_id = self
+            .inner
+            .invoke_id
+            .fetch_add(1, Ordering::Relaxed)
+            .wrapping_add(1);
+        let buffer = request_frame(
+            &self.inner.ams_targed_address,
+            &lock(&self.inner.ams_source_address),
+            invoke_id,
+            request,
+        )?;
+
+        let (tx, rx) = oneshot::channel::<ClientResult<Response>>();
+        lock(&self.inner.sender_tables)
+            .general
+            .insert(invoke_id, tx);
+        //Removes the sender again if writing fails or the future is dropped
+        let pending = PendingResponse {
+            invoke_id,
+            rx,
+            sender_tables: Some(self.inner.sender_tables.clone()),
+        };
+
+        let mut writer = self.inner.writer.lock().await;
+        match writer.as_mut() {
+            Some(w) => w.write_all(&buffer).await?,
+            None => return Err(ClientError::NotConnected),
+        }
+        Ok(pending)
+    }
+
+    /// Read a var value by it's name.
+    /// Returns ReadResponse
+    pub async fn read_by_name(&self, var_name: &str, len: u32) -> ClientResult<ReadResponse> {
+        let handle = self.get_var_handle(var_name).await?;
+        let request = Request::Read(request_factory::get_read_request(handle, len));
+        let response = self.request(request).await?;
+        let read_response: ReadResponse = response.try_into()?;
+        Ok(read_response)
+    }
+
+    /// Read a var value by it's name and decode it into T.
+    /// The read length is taken from T. Fails if the host returns an error.
+    pub async fn read_value<T: AdsType>(&self, var_name: &str) -> ClientResult<T> {
+        let response = self.read_by_name(var_name, T::SIZE as u32).await?;
+        if response.result != AdsError::ErrNoError {
+            return Err(ClientError::Ads(response.result));
+        }
+        T::decode(&response.data)
+    }
+
+    /// Read a list of var values by name. This will bundle all requested variables into a single request.
+    /// Returns the data or the error of every var in the order of var_list (see Client::sumup_read_by_name).
+    pub async fn sumup_read_by_name(
+        &self,
+        var_list: &[(&str, u32)],
+    ) -> ClientResult<SumupResult<Vec<u8>>> {
+        let var_names: Vec<String> = var_list.iter().map(|(name, _)| name.to_string()).collect();
+        let handles = self.sumup_get_var_handle(&var_names).await?;
+
+        let (mut results, requested, requests) =
+            sumup_by_name(var_list, &handles, Vec::new(), |handle, length| {
+                get_read_request(handle, *length)
+            })?;
+        if requests.is_empty() {
+            return Ok(results);
+        }
+
+        let (sumup_requests, counts): (Vec<Request>, Vec<usize>) =
+            sumup_read_chunks(&requests)?.into_iter().unzip();
+        let responses = self.request_pipelined(sumup_requests).await?;
+        store_sumup_read_responses(&mut results, requested, responses, counts)?;
+        Ok(results)
+    }
+
+    /// Write by name
+    /// Returns WriteResponse
+    pub async fn write_by_name(
+        &self,
+        var_name: &str,
+        data: Vec<u8>,
+    ) -> ClientResult<WriteResponse> {
+        let handle = self.get_var_handle(var_name).await?;
+        let request = Request::Write(request_factory::get_write_request(handle, data));
+        let response = self.request(request).await?;
+        let write_response: WriteResponse = response.try_into()?;
+        Ok(write_response)
+    }
+
+    /// Write a value of type T by name.
+    /// The data length is taken from T. Fails if the host returns an error.
+    pub async fn write_value<T: AdsType>(&self, var_name: &str, value: &T) -> ClientResult<()> {
+        let response = self.write_by_name(var_name, value.to_bytes()?).await?;
+        if response.result != AdsError::ErrNoError {
+            return Err(ClientError::Ads(response.result));
+        }
+        Ok(())
+    }
+
+    /// Write a list of var values by name. This will bundle all the write data into a single write request.
+    /// Returns the result of every var in the order of var_list (see Client::sumup_write_by_name).
+    pub async fn sumup_write_by_name(
+        &self,
+        var_list: &[(&str, Vec<u8>)],
+    ) -> ClientResult<SumupResult<()>> {
+        let var_names: Vec<String> = var_list.iter().map(|(name, _)| name.to_string()).collect();
+        let handles = self.sumup_get_var_handle(&var_names).await?;
+
+        let (mut results, requested, requests) =
+            sumup_by_name(var_list, &handles, (), |handle, data| {
+                get_write_request(handle, data.clone())
+            })?;
+        if requests.is_empty() {
+            return Ok(results);
+        }
+
+        let (sumup_requests, counts): (Vec<Request>, Vec<usize>) =
+            sumup_write_chunks(&requests)?.into_iter().unzip();
+        let responses = self.request_pipelined(sumup_requests).await?;
+        store_sumup_write_responses(&mut results, requested, responses, counts)?;
+        Ok(results)
+    }
+
+    /// Read device info
+    /// Returns ReadDeviceInfoResponse
+    pub async fn read_device_info(&self) -> ClientResult<ReadDeviceInfoResponse> {
+        let request = Request::ReadDeviceInfo(ReadDeviceInfoRequest::new());
+        let response = self.request(request).await?;
+        let device_info_response: ReadDeviceInfoResponse = response.try_into()?;
+        Ok(device_info_response)
+    }
+
+    /// Read PLC state
+    /// Returns ReadStateResponse
+    pub async fn read_state(&self) -> ClientResult<ReadStateResponse> {
+        let request = Request::ReadState(ReadStateRequest::new());
+        let response = self.request(request).await?;
+        let device_state: ReadStateResponse = response.try_into()?;
+        Ok(device_state)
+    }
+
+    /// Write control
+    /// Returns WriteControlResponse
+    pub async fn write_control(
+        &self,
+        ads_state: AdsState,
+        device_state: u16,
+    ) -> ClientResult<WriteControlResponse> {
+        let request = Request::WriteControl(request_factory::get_write_control_request(
+            ads_state,
+            device_state,
+        ));
+        let response = self.request(request).await?;
+        let write_control_response: WriteControlResponse = response.try_into()?;
+        Ok(write_control_response)
+    }
+
+    /// Read and write data
+    /// Returns ReadWriteResponse
+    pub async fn read_write(
+        &self,
+        index_offset: u32,
+        read_len: u32,
+        write_data: Vec<u8>,
+    ) -> ClientResult<ReadWriteResponse> {
+        let request = Request::ReadWrite(request_factory::get_read_write_request(
+            index_offset,
+            read_len,
+            write_data,
+        ));
+        let response = self.request(request).await?;
+        let read_write_response: ReadWriteResponse = response.try_into()?;
+        Ok(read_write_response)
+    }
+
+    /// Add device notification to receive updated values at value change or at a certain time interfall
+    /// Returns a NotificationStream. Every call adds a new notification, so a var can be watched by several streams.
+    pub async fn add_device_notification(
+        &self,
+        var_name: &str,
+        length: u32,
+        transmission_mode: AdsTransMode,
+        max_delay: u32,
+        cycle_time: u32,
+    ) -> ClientResult<NotificationStream> {
+        let handle = self.get_var_handle(var_name).await?;
+        let request = Request::AddDeviceNotification(request_factory::get_add_device_notification(
+            handle,
+            length,
+            transmission_mode,
+            max_delay,
+            cycle_time,
+        ));
+
+        let handle = notification_handle(self.request(request).await?)?;
+        Ok(self.activate_notification(var_name, handle))
+    }
+
+    /// Pass the sender of an added notification to the reader task and keep the notification for deleting
+    fn activate_notification(&self, var_name: &str, handle: u32) -> NotificationStream {
+        let (tx, rx) = mpsc::unbounded_channel::<NotificationResult>();
+        lock(&self.inner.sender_tables)
+            .device_notification
+            .insert(handle, tx);
+        let id = self.inner.notification_id.fetch_add(1, Ordering::Relaxed);
+        lock(&self.inner.notifications).insert(id, (var_name.to_string(), handle));
+        NotificationStream {
+            rx,
+            id,
+            client: Arc::downgrade(&self.inner),
+        }
+    }
+
+    /// Ids and handles of the notifications of a var, oldest first
+    fn notifications_of(&self, var_name: &str) -> Vec<(u64, u32)> {
+        let mut notifications: Vec<(u64, u32)> = lock(&self.inner.notifications)
+            .iter()
+            .filter(|(_, (name, _))| name == var_name)
+            .map(|(id, (_, handle))| (*id, *handle))
+            .collect();
+        notifications.sort_unstable();
+        notifications
+    }
+
+    /// Remove the notifications of a var and their senders. Returns their handles, oldest first.
+    fn remove_notifications_of(&self, var_name: &str) -> Vec<u32> {
+        let notifications = self.notifications_of(var_name);
+        let mut active = lock(&self.inner.notifications);
+        let mut tables = lock(&self.inner.sender_tables);
+        notifications
+            .into_iter()
+            .filter_map(|(id, _)| active.remove(&id))
+            .map(|(_, handle)| {
+                tables.device_notification.remove(&handle);
+                handle
+            })
+            .collect()
+    }
+
+    /// Add device notifications for a list of vars (name, length) with sumup requests (ADSIGRP_SUMUP_ADDDEVNOTE).
+    /// Returns a NotificationStream or the error of the host for every var in the order of var_list
+    /// (see Client::sumup_add_device_notifications).
+    pub async fn sumup_add_device_notifications(
+        &self,
+        var_list: &[(&str, u32)],
+        transmission_mode: AdsTransMode,
+        max_delay: u32,
+        cycle_time: u32,
+    ) -> ClientResult<SumupResult<NotificationStream>> {
+        let var_names: Vec<String> = var_list.iter().map(|(name, _)| name.to_string()).collect();
+        let handles = self.sumup_get_var_handle(&var_names).await?;
+        //Check before anything is added on the host
+        if let Some(name) = var_names.iter().find(|name| !handles.contains_key(*name)) {
+            return Err(ClientError::HandleNotFound { name: name.clone() });
+        }
+
+        let requests = sumup_add_device_notification_requests(
+            var_list,
+            &handles,
+            transmission_mode,
+            max_delay,
+            cycle_time,
+        );
+        let (sumup_requests, counts): (Vec<Request>, Vec<usize>) =
+            sumup_add_device_notification_chunks(&requests)?
+                .into_iter()
+                .unzip();
+        let responses = self.request_pipelined(sumup_requests).await?;
+        let outcomes =
+            match sumup_add_device_notification_outcomes(var_list, &handles, responses, counts)? {
+                Ok(outcomes) => outcomes,
+                Err(added) => {
+                    let _ = self.sumup_delete_notification_handles(&added).await;
+                    return Err(ClientError::ProtocolViolation(
+                        "Sumup add device notification response too short".to_string(),
+                    ));
+                }
+            };
+
+        Ok(var_list
+            .iter()
+            .zip(outcomes)
+            .map(|((name, _), outcome)| {
+                let result = match outcome {
+                    Ok((AdsError::ErrNoError, handle)) => {
+                        Ok(self.activate_notification(name, handle))
+                    }
+                    Ok((error, _)) | Err(error) => Err(error),
+                };
+                (name.to_string(), result)
+            })
+            .collect())
+    }
+
+    /// Delete notifications on the host with sumup requests. Returns the result of every handle.
+    async fn sumup_delete_notification_handles(
+        &self,
+        handles: &[u32],
+    ) -> ClientResult<Vec<AdsError>> {
+        let (sumup_requests, counts): (Vec<Request>, Vec<usize>) =
+            sumup_delete_device_notification_chunks(handles)?
+                .into_iter()
+                .unzip();
+        sumup_delete_device_notification_results(
+            self.request_pipelined(sumup_requests).await?,
+            counts,
+        )
+    }
+
+    /// Delete the device notifications of a list of vars with sumup requests (ADSIGRP_SUMUP_DELDEVNOTE).
+    /// All notifications of a var are deleted. Returns the result of the host for every var in the order of var_names
+    /// (see Client::sumup_delete_device_notifications).
+    /// Fails with HandleNotFound without deleting anything if a var has no notification.
+    pub async fn sumup_delete_device_notifications(
+        &self,
+        var_names: &[&str],
+    ) -> ClientResult<SumupResult<()>> {
+        if let Some(name) = var_names
+            .iter()
+            .find(|name| self.notifications_of(name).is_empty())
+        {
+            return Err(ClientError::HandleNotFound {
+                name: name.to_string(),
+            });
+        }
+        let var_handles: Vec<Vec<u32>> = var_names
+            .iter()
+            .map(|name| self.remove_notifications_of(name))
+            .collect();
+        let handles: Vec<u32> = var_handles.iter().flatten().copied().collect();
+
+        let mut responses = self
+            .sumup_delete_notification_handles(&handles)
+            .await?
+            .into_iter();
+        Ok(var_names
+            .iter()
+            .zip(var_handles)
+            .map(|(name, var_handles)| {
+                let mut result = Ok(());
+                //The number of responses has been checked by the sumup parser
+                for error in responses.by_ref().take(var_handles.len()) {
+                    if error != AdsError::ErrNoError && result.is_ok() {
+                        result = Err(error);
+                    }
+                }
+                (name.to_string(), result)
+            })
+            .collect())
+    }
+
+    /// Release the device notifications of a var on the host. All notifications of the var are deleted.
+    /// Returns DeleteDeviceNotificationResponse (the first failed one if the host rejected a deletion)
+    pub async fn delete_device_notification(
+        &self,
+        var_name: &str,
+    ) -> ClientResult<DeleteDeviceNotificationResponse> {
+        let handles = self.remove_notifications_of(var_name);
+        let mut result: Option<DeleteDeviceNotificationResponse> = None;
+        for handle in handles {
+            let request = Request::DeleteDeviceNotification(
+                request_factory::get_delete_device_notification(handle),
+            );
+            let response: DeleteDeviceNotificationResponse =
+                self.request(request).await?.try_into()?;
+            if !matches!(&result, Some(r) if r.result != AdsError::ErrNoError) {
+                result = Some(response);
+            }
+        }
+        result.ok_or_else(|| ClientError::HandleNotFound {
+            name: var_name.to_string(),
+        })
+    }
+
+    /// Delete every device notification of the client on the host.
+    /// Returns the first error, the remaining notifications are deleted anyway.
+    pub async fn delete_all_notifications(&self) -> ClientResult<()> {
+        let handles: Vec<u32> = lock(&self.inner.notifications)
+            .drain()
+            .map(|(_, (_, handle))| handle)
+            .collect();
+        let mut result = Ok(());
+        for handle in handles {
+            let deleted = self.delete_notification_handle(handle).await;
+            if result.is_ok() {
+                result = deleted;
+            }
+        }
+        result
+    }
+
+    /// Forget the stream of a notification and delete the notification on the host
+    async fn delete_notification_handle(&self, handle: u32) -> ClientResult<()> {
+        lock(&self.inner.sender_tables)
+            .device_notification
+            .remove(&handle);
+        let request = Request::DeleteDeviceNotification(
+            request_factory::get_delete_device_notification(handle),
+        );
+        let response: DeleteDeviceNotificationResponse = self.request(request).await?.try_into()?;
+        if response.result != AdsError::ErrNoError {
+            return Err(ClientError::Ads(response.result));
+        }
+        Ok(())
+    }
+
+    /// Returns the notification handle for a given variable name if available in the client.
+    /// If the var has several notifications the handle of the oldest one is returned.
+    pub fn get_notification_handle(&self, var_name: &str) -> ClientResult<u32> {
+        if let Some((_, (_, handle))) = lock(&self.inner.notifications)
+            .iter()
+            .filter(|(_, (name, _))| name == var_name)
+            .min_by_key(|(id, _)| **id)
+        {
+            return Ok(*handle);
+        }
+        Err(ClientError::HandleNotFound {
+            name: var_name.to_string(),
+        })
+    }
+
+    /// Get a var handle by name.
+    /// If the handle is already known, it will be returned from the handle list.
+    /// If the handle is not known, a request will be sent to the host to get the handle.
+    pub async fn get_var_handle(&self, var_name: &str) -> ClientResult<u32> {
+        if let Some(handle) = lock(&self.inner.handle_list).get(var_name) {
+            return Ok(*handle);
+        }
+        let request = Request::ReadWrite(get_var_handle_request(var_name));
+        let handle = var_handle(var_name, self.request(request).await?)?;
+        Ok(self.cache_var_handle(var_name, handle).await)
+    }
+
+    /// Store a requested handle in the handle list.
+    /// If another task stored a handle for the same var in the meantime, the new one is released and the cached one returned.
+    async fn cache_var_handle(&self, var_name: &str, handle: u32) -> u32 {
+        let cached = *lock(&self.inner.handle_list)
+            .entry(var_name.to_string())
+            .or_insert(handle);
+        if cached != handle {
+            let _ = self
+                .request(Request::Write(request_factory::get_release_handle_request(
+                    handle,
+                )))
+                .await;
+        }
+        cached
+    }
+
+    /// Get the var handles for a list of names. Unknown handles are requested with a single sumup request.
+    /// Returns the handle or the error code of the host for every name.
+    async fn sumup_get_var_handle(
+        &self,
+        var_names: &[String],
+    ) -> ClientResult<HashMap<String, Result<u32, AdsError>>> {
+        let mut do_request: Vec<String> = Vec::new();
+        let mut handles: HashMap<String, Result<u32, AdsError>> = HashMap::new();
+        {
+            let handle_list = lock(&self.inner.handle_list);
+            for var in var_names {
+                if let Some(handle) = handle_list.get(var) {
+                    handles.insert(var.clone(), Ok(*handle));
+                } else {
+                    do_request.push(var.clone());
+                }
+            }
+        }
+        if do_request.is_empty() {
+            return Ok(handles);
+        }
+
+        let requests: Vec<ReadWriteRequest> = do_request
+            .iter()
+            .map(|var| get_var_handle_request(var))
+            .collect();
+        let requested_handles =
+            sumup_var_handles(&do_request, self.sumup_read_write(&requests).await?)?;
+        for (name, handle) in requested_handles {
+            let handle = match handle {
+                Ok(handle) => Ok(self.cache_var_handle(&name, handle).await),
+                Err(error) => Err(error),
+            };
+            handles.insert(name, handle);
+        }
+        Ok(handles)
+    }
+
+    /// Send ReadWrite requests bundled into sumup requests (ADSIGRP_SUMUP_READWRITE).
+    /// Returns the read data or the error of the host for every request in the order of requests.
+    pub async fn sumup_read_write(
+        &self,
+        requests: &[ReadWriteRequest],
+    ) -> ClientResult<Vec<Result<Vec<u8>, AdsError>>> {
+        let (sumup_requests, counts): (Vec<Request>, Vec<usize>) =
+            sumup_read_write_chunks(requests)?.into_iter().unzip();
+        sumup_read_write_results(self.request_pipelined(sumup_requests).await?, counts)
+    }
+
+    /// Release var handle
+    pub async fn release_handle(&self, var_name: &str) -> ClientResult<WriteResponse> {
+        let handle = lock(&self.inner.handle_list).get(var_name).copied();
+        if let Some(handle) = handle {
+            let request = Request::Write(request_factory::get_release_handle_request(handle));
+            let response: WriteResponse = self.request(request).await?.try_into()?;
+            lock(&self.inner.handle_list).remove(var_name);
+            return Ok(response);
+        }
+        Err(ClientError::HandleNotFound {
+            name: var_name.to_string(),
+        })
+    }
+
+    ///Check if stream disconnected
+    async fn check_tcp_stream(&self, response: &ClientResult<Response>) {
+        if let Err(ClientError::NotConnected) = response {
+            if let Some(mut writer) = self.inner.writer.lock().await.take() {
+                let _ = writer.shutdown().await;
+            }
+            self.inner.forget_session();
+        }
+    }
+}
+
+/// Response to a sent request. Removes the sender from the reader task
+/// if it is dropped before the response arrived (timeout, cancelled future).
+struct PendingResponse {
+    invoke_id: u32,
+    rx: oneshot::Receiver<ClientResult<Response>>,
+    sender_tables: Option<Arc<Mutex<SenderTables>>>,
+}
+
+impl PendingResponse {
+    /// Hand the receiver to the caller. The reader task removes the sender when the response arrives.
+    fn into_receiver(mut self) -> oneshot::Receiver<ClientResult<Response>> {
+        self.sender_tables = None;
+        let (_, rx) = oneshot::channel();
+        std::mem::replace(&mut self.rx, rx)
+    }
+}
+
+impl Drop for PendingResponse {
+    fn drop(&mut self) {
+        if let Some(sender_tables) = &self.sender_tables {
+            lock(sender_tables).general.remove(&self.invoke_id);
+        }
+    }
+}
+
+/// Receive responses and notifications until the connection is lost.
+/// The writer and the session of the client are cleared then, so requests fail with NotConnected
+/// and connect() opens a new connection without the var handles and notifications of the old one.
+async fn run_reader_task(
+    mut stream: OwnedReadHalf,
+    sender_tables: Arc<Mutex<SenderTables>>,
+    writer: Arc<tokio::sync::Mutex<Option<OwnedWriteHalf>>>,
+    health: Arc<Mutex<ReaderHealth>>,
+    client: Weak<AsyncClientInner>,
+) {
+    let error = read_packets(&mut stream, &sender_tables, &health).await;
+    //Hold the writer lock so connect() can't open the next connection before the session is cleared
+    let mut writer = writer.lock().await;
+    if let Some(mut writer) = writer.take() {
+        let _ = writer.shutdown().await;
+    }
+    notify_connection_down(&sender_tables);
+    if let Some(inner) = client.upgrade() {
+        inner.forget_session();
+    }
+    drop(writer);
+    record_error(&health, error.to_string(), false);
+    lock(&health).state = ReaderState::ConnectionLost;
+}
+
+/// Forward the received packets. Returns the error which ended the connection.
+async fn read_packets<R: AsyncRead + Unpin>(
+    stream: &mut R,
+    sender_tables: &Mutex<SenderTables>,
+    health: &Mutex<ReaderHealth>,
+) -> std::io::Error {
+    loop {
+        let mut ams_header = match read(stream).await {
+            Ok(Ok(h)) => h,
+            Ok(Err(packet)) => {
+                record_error(health, packet.to_error().to_string(), true);
+                //Report the malformed packet to the waiting caller
+                if let Some(id) = packet.invoke_id {
+                    if let Some(tx) = lock(sender_tables).general.remove(&id) {
+                        let _ = tx.send(Err(packet.to_error()));
+                    }
+                }
+                continue;
+            }
+            Err(e) => return e,
+        };
+
+        //Send data to client
+        match ams_header.ads_error() {
+            AdsError::ErrNoError => {
+                if let Err(e) = forward_data(&mut ams_header, sender_tables) {
+                    record_error(health, e.to_string(), true);
+                }
+            }
+            AdsError::ErrPortNotConnected => notify_connection_down(sender_tables),
+            error => {
+                let invoke_id = ams_header.invoke_id();
+                if let Some(tx) = lock(sender_tables).general.remove(&invoke_id) {
+                    let _ = tx.send(Err(ClientError::Ams {
+                        invoke_id,
+                        error: error.clone(),
+                    }));
+                }
+            }
+        };
+    }
+}
+
+/// Read the next AMS packet. The inner result is an error if the packet can't be decoded.
+async fn read<R: AsyncRead + Unpin>(
+    stream: &mut R,
+) -> Result<Result<AmsHeader, MalformedPacket>, std::io::Error> {
+    let mut buf = vec![0; AMS_TCP_HEADER_SIZE]; //reserved + length
+    stream.read_exact(&mut buf).await?;
+    let mut buf: Vec<u8> = vec![0; ams_packet_length(&buf)?];
+    stream.read_exact(&mut buf).await?;
+    Ok(parse_packet(&buf))
+}
+
+/// Forward a response or the samples of a notification to the receivers.
+/// A response which can't be decoded is reported to the waiting caller. Fails if a notification can't be decoded.
+fn forward_data(
+    ams_header: &mut AmsHeader,
+    sender_tables: &Mutex<SenderTables>,
+) -> ClientResult<()> {
+    let mut tables = lock(sender_tables);
+    match ams_header.command_id() {
+        CommandID::DeviceNotification => {
+            let ads_notification = extract_notification_stream(ams_header)?;
+            for header in &ads_notification.ads_stamp_headers {
+                for sample in &header.notification_samples {
+                    let handle = sample.notification_handle;
+                    if let Some(tx) = tables.device_notification.get(&handle) {
+                        if tx.send(Ok((sample.clone(), header.time_stamp))).is_err() {
+                            tables.device_notification.remove(&handle);
+                        }
+                    }
+                }
+            }
+        }
+        _ => {
+            let response = extract_response(ams_header);
+            let decode_error = response.as_ref().err().map(|e| e.to_string());
+            if let Some(tx) = tables.general.remove(&ams_header.invoke_id()) {
+                let _ = tx.send(response);
+            }
+            if let Some(e) = decode_error {
+                return Err(ClientError::ProtocolViolation(e));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn notify_connection_down(sender_tables: &Mutex<SenderTables>) {
+    let mut tables = lock(sender_tables);
+    for (_, tx) in tables.general.drain() {
+        let _ = tx.send(Err(ClientError::NotConnected));
+    }
+    tables
+        .device_notification
+        .retain(|_, tx| tx.send(Err(ClientError::NotConnected)).is_ok());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client() -> AsyncClient {
+        AsyncClient::new(
+            AmsAddress::new(AmsNetId::from([127, 0, 0, 1, 1, 1]), 851),
+            None,
+        )
+    }
+
+    /// AMS/TCP frame with zero addresses
+    fn frame(command_id: u16, state_flags: u16, invoke_id: u32, data: &[u8]) -> Vec<u8> {
+        let chunks: &[&[u8]] = &[
+            &[0, 0],                                 //reserved
+            &(32 + data.len() as u32).to_le_bytes(), //length
+            &[0; 16],                                //target and source
+            &command_id.to_le_bytes(),
+            &state_flags.to_le_bytes(),
+            &(data.len() as u32).to_le_bytes(),
+            &0u32.to_le_bytes(), //error
+            &invoke_id.to_le_bytes(),
+            data,
+        ];
+        chunks.concat()
+    }
+
+    #[test]
+    fn dropped_pending_response_removes_its_sender() {
+        let sender_tables = Arc::new(Mutex::new(SenderTables::default()));
+        let pending = |invoke_id| {
+            let (tx, rx) = oneshot::channel();
+            lock(&sender_tables).general.insert(invoke_id, tx);
+            PendingResponse {
+                invoke_id,
+                rx,
+                sender_tables: Some(sender_tables.clone()),
+            }
+        };
+        let dropped = pending(1);
+        let handed_out = pending(2).into_receiver();
+        drop(dropped);
+        //The sender of a receiver handed to the caller is removed by the reader task
+        let tables = lock(&sender_tables);
+        assert!(!tables.general.contains_key(&1));
+        assert!(tables.general.contains_key(&2));
+        drop(handed_out);
+    }
+
+    #[tokio::test]
+    async fn reader_routes_responses_and_notifications() {
+        let (mut reader, mut peer) = tokio::io::duplex(1024);
+        let sender_tables = Arc::new(Mutex::new(SenderTables::default()));
+        let health = Arc::new(Mutex::new(ReaderHealth::default()));
+        let (tx_response, rx_response) = oneshot::channel();
+        let (tx_notification, mut rx_notification) = mpsc::unbounded_channel();
+        {
+            let mut tables = lock(&sender_tables);
+            tables.general.insert(7, tx_response);
+            tables.device_notification.insert(3, tx_notification);
+        }
+        let tables = sender_tables.clone();
+        let task_health = health.clone();
+        let task =
+            tokio::spawn(async move { read_packets(&mut reader, &tables, &task_health).await });
+
+        let chunks: &[&[u8]] = &[
+            &22u32.to_le_bytes(), //length
+            &1u32.to_le_bytes(),  //stamps
+            &42u64.to_le_bytes(), //time stamp
+            &1u32.to_le_bytes(),  //samples
+            &3u32.to_le_bytes(),  //notification handle
+            &2u32.to_le_bytes(),  //sample size
+            &[0xAB, 0xCD],
+        ];
+        let notification = chunks.concat();
+        peer.write_all(&frame(8, 0x0004, 0, &notification))
+            .await
+            .unwrap();
+        //ReadState response: result, Run, device state
+        peer.write_all(&frame(4, 0x0005, 7, &[0, 0, 0, 0, 5, 0, 9, 0]))
+            .await
+            .unwrap();
+
+        let (sample, time_stamp) = rx_notification.recv().await.unwrap().unwrap();
+        assert_eq!(time_stamp, 42);
+        assert_eq!(sample.notification_handle, 3);
+        assert_eq!(sample.data, vec![0xAB, 0xCD]);
+        let response: ReadStateResponse = rx_response.await.unwrap().unwrap().try_into().unwrap();
+        assert_eq!(response.device_state, 9);
+        assert!(lock(&sender_tables).general.is_empty());
+
+        //The reader returns the error which ended the connection
+        drop(peer);
+        let error = task.await.unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(lock(&health).decode_errors, 0);
+    }
+
+    #[tokio::test]
+    async fn duplicate_var_handle_keeps_the_cached_one() {
+        let client = client();
+        lock(&client.inner.handle_list).insert("MAIN.a".to_string(), 1);
+        //The release request of the duplicate fails without connection and is ignored
+        assert_eq!(client.cache_var_handle("MAIN.a", 2).await, 1);
+        assert_eq!(client.cache_var_handle("MAIN.b", 3).await, 3);
+        let handle_list = lock(&client.inner.handle_list);
+        assert_eq!(handle_list["MAIN.a"], 1);
+        assert_eq!(handle_list["MAIN.b"], 3);
+    }
+}
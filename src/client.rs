@@ -1,5 +1,20 @@
-use crate::reader::run_reader_thread;
+use crate::ads_type::AdsType;
+use crate::connection_state::{ConnectionEvent, ConnectionMonitor, ConnectionState, StateWatch};
+use crate::data_type::{DataType, DataTypeKind, DataTypeTable};
+use crate::dispatcher::{Dispatcher, NotificationCallback, NotificationSink};
+use crate::error::ClientError;
+use crate::notification::{
+    NotificationSettings, NotificationTarget, NotificationToken, Subscription, TypedSubscription,
+};
+use crate::plc_value::PlcValue;
+use crate::read_group::ReadGroup;
+use crate::reader::{
+    run_reader_thread, NotificationUpdate, AMS_HEADER_INVOKE_ID, AMS_TCP_HEADER_SIZE,
+};
+use crate::reconnect::{run_reconnect_thread, ReconnectPolicy, SessionEvent};
 use crate::request_factory::{self, *};
+use crate::sumup::*;
+use crate::symbol::{SymbolTable, SymbolUploadInfo};
 use ads_proto::error::AdsError;
 use ads_proto::proto::ads_state::AdsState;
 use ads_proto::proto::ads_transition_mode::AdsTransMode;
@@ -7,22 +22,19 @@ use ads_proto::proto::ams_address::{AmsAddress, AmsNetId};
 use ads_proto::proto::ams_header::{AmsHeader, AmsTcpHeader};
 use ads_proto::proto::proto_traits::*;
 use ads_proto::proto::request::{
-    ReadDeviceInfoRequest, ReadRequest, ReadStateRequest, Request, WriteRequest,
+    ReadDeviceInfoRequest, ReadStateRequest, ReadWriteRequest, Request, WriteRequest,
 };
 use ads_proto::proto::response::Response;
 use ads_proto::proto::response::*;
 use ads_proto::proto::state_flags::StateFlags;
-use ads_proto::proto::sumup::sumup_request::{SumupReadRequest, SumupWriteRequest};
-use ads_proto::proto::sumup::sumup_response::{SumupReadResponse, SumupWriteResponse};
-use anyhow::Error;
-use anyhow::{anyhow, Result};
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::collections::HashMap;
 use std::io::Write;
 use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpStream};
-use std::str::FromStr;
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::time::Duration;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
 
 /// UDP ADS-Protocol port discovery
 pub const ADS_UDP_SERVER_PORT: u16 = 48899;
@@ -30,95 +42,261 @@ pub const ADS_UDP_SERVER_PORT: u16 = 48899;
 pub const ADS_TCP_SERVER_PORT: u16 = 48898;
 /// ADS-Protocol port secured
 pub const ADS_SECURE_TCP_SERVER_PORT: u16 = 8016;
+/// Request a local AMS port from the router (route = None)
+pub(crate) const AMS_PORT_CONNECT_REQUEST: [u8; 8] = [0, 16, 2, 0, 0, 0, 0, 0];
+/// Router response to AMS_PORT_CONNECT_REQUEST. The AMS address starts at byte 6
+pub(crate) const AMS_PORT_CONNECT_RESPONSE_LEN: usize = 14;
+/// Default time to wait for a response (see Client::set_timeout)
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
 
-pub type ClientResult<T> = Result<T, anyhow::Error>;
+pub type ClientResult<T> = Result<T, ClientError>;
+/// Result of every var of a sumup request in the order of the request
+pub type SumupResult<T> = Vec<(String, Result<T, AdsError>)>;
 type TxGeneral = Sender<(u32, Sender<ClientResult<Response>>)>;
-type TimeStamp = u64;
-type TxNotification = Sender<(u32, Sender<ClientResult<(AdsNotificationSample, TimeStamp)>>)>;
+pub type TimeStamp = u64;
+/// Receiver of the samples of a device notification
+pub type NotificationReceiver = Receiver<ClientResult<(AdsNotificationSample, TimeStamp)>>;
+type TxNotification = Sender<NotificationUpdate>;
 type TxStreamUpdate = Sender<TcpStream>;
-#[derive(Debug)]
+type TxCancel = Sender<u32>;
+
+/// State of the reader thread which receives responses and notifications
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReaderState {
+    /// connect() has not been called yet
+    #[default]
+    NotStarted,
+    /// Receiving data
+    Running,
+    /// The TCP connection failed. Waiting for a new connection (connect()).
+    ConnectionLost,
+    /// The reader thread ended. It is restarted by connect().
+    Stopped,
+}
+
+/// Status of the reader thread. See Client::reader_health
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReaderHealth {
+    pub state: ReaderState,
+    /// Number of received packets which could not be decoded
+    pub decode_errors: u64,
+    /// Last error seen by the reader thread
+    pub last_error: Option<String>,
+}
+
+/// ADS client handle.
+/// The client is Send + Sync and cheap to clone. All clones share one TCP connection and one reader thread,
+/// so several threads can have requests in flight at the same time. Responses are matched by invoke id.
+#[derive(Debug, Clone)]
 pub struct Client {
+    inner: Arc<ClientInner>,
+}
+
+/// Client reference which does not keep the client alive (used by the reconnect thread)
+#[derive(Debug, Clone)]
+pub(crate) struct WeakClient {
+    inner: Weak<ClientInner>,
+}
+
+impl WeakClient {
+    pub(crate) fn upgrade(&self) -> Option<Client> {
+        self.inner.upgrade().map(|inner| Client { inner })
+    }
+}
+
+#[derive(Debug)]
+struct ClientInner {
     route: Option<Ipv4Addr>,
     ams_targed_address: AmsAddress,
-    ams_source_address: AmsAddress,
+    ams_source_address: Mutex<AmsAddress>,
+    connection: Mutex<Connection>,
+    invoke_id: AtomicU32,
+    timeout: Mutex<Duration>,
+    reader_health: Arc<Mutex<ReaderHealth>>,
+    connection_monitor: Mutex<ConnectionMonitor>,
+    reconnect_policy: Mutex<Option<ReconnectPolicy>>,
+    symbol_version: Mutex<Option<u8>>,
+    /// Notification of the symbol version. See watch_symbol_version
+    symbol_version_watch: Mutex<Option<NotificationToken>>,
+    handle_list: Mutex<HashMap<String, u32>>,
+    /// Incremented whenever cached handles are released or replaced
+    handle_generation: AtomicU32,
+    /// Active device notifications by notification id. A var can have several notifications.
+    notifications: Mutex<HashMap<u64, ActiveNotification>>,
+    notification_id: AtomicU64,
+    /// Runs the callbacks of add_device_notification_with_callback
+    dispatcher: Mutex<Dispatcher>,
+    symbol_table: Mutex<Option<SymbolTable>>,
+    data_type_table: Mutex<Option<DataTypeTable>>,
+}
+
+/// TCP stream and the channels to the reader thread.
+/// Locked while a request is registered and written so frames are never interleaved.
+#[derive(Debug, Default)]
+struct Connection {
     stream: Option<TcpStream>,
-    invoke_id: u32,
     tx_general: Option<TxGeneral>,
     tx_notification: Option<TxNotification>,
     tx_stream_update: Option<TxStreamUpdate>,
+    tx_cancel: Option<TxCancel>,
+    /// Events for the reconnect thread
+    tx_session: Option<Sender<SessionEvent>>,
     thread_started: bool,
-    handle_list: HashMap<String, u32>,
-    notification_handle_list: HashMap<String, u32>,
 }
 
-impl Drop for Client {
+/// Device notification added by the client.
+/// The parameters are kept to add the notification again after a reconnect.
+#[derive(Debug, Clone)]
+struct ActiveNotification {
+    target: NotificationTarget,
+    handle: u32,
+    length: u32,
+    transmission_mode: AdsTransMode,
+    max_delay: u32,
+    cycle_time: u32,
+    tx: NotificationSink,
+}
+
+impl Drop for ClientInner {
     fn drop(&mut self) {
-        if let Some(s) = &self.stream {
+        if let Some(s) = &lock(&self.connection).stream {
+            //Free the notification slots of the host. The responses are not awaited.
+            let mut stream = s;
+            let _ = stream.write_all(&self.delete_notification_frames());
             let _ = s.shutdown(Shutdown::Both);
         }
     }
 }
 
+impl ClientInner {
+    /// DeleteDeviceNotification requests for all active notifications
+    fn delete_notification_frames(&self) -> Vec<u8> {
+        delete_notification_frames(
+            &self.ams_targed_address,
+            &lock(&self.ams_source_address),
+            &self.invoke_id,
+            lock(&self.notifications).drain().map(|(_, n)| n.handle),
+        )
+    }
+}
+
 impl Client {
     /// Setup a new client. This will will not yet connect to the targed.
     /// Call connect() after creation.
     pub fn new(ams_targed_address: AmsAddress, route: Option<Ipv4Addr>) -> Self {
         Client {
-            route,
-            ams_targed_address,
-            ams_source_address: AmsAddress::new(AmsNetId::from([0, 0, 0, 0, 0, 0]), 0),
-            stream: None,
-            invoke_id: 0,
-            tx_general: None,
-            tx_notification: None,
-            tx_stream_update: None,
-            thread_started: false,
-            handle_list: HashMap::new(),
-            notification_handle_list: HashMap::new(),
+            inner: Arc::new(ClientInner {
+                route,
+                ams_targed_address,
+                ams_source_address: Mutex::new(AmsAddress::new(
+                    AmsNetId::from([0, 0, 0, 0, 0, 0]),
+                    0,
+                )),
+                connection: Mutex::new(Connection::default()),
+                invoke_id: AtomicU32::new(0),
+                timeout: Mutex::new(DEFAULT_REQUEST_TIMEOUT),
+                reader_health: Arc::new(Mutex::new(ReaderHealth::default())),
+                connection_monitor: Mutex::new(ConnectionMonitor::default()),
+                reconnect_policy: Mutex::new(None),
+                symbol_version: Mutex::new(None),
+                symbol_version_watch: Mutex::new(None),
+                handle_list: Mutex::new(HashMap::new()),
+                handle_generation: AtomicU32::new(0),
+                notifications: Mutex::new(HashMap::new()),
+                notification_id: AtomicU64::new(0),
+                dispatcher: Mutex::new(Dispatcher::default()),
+                symbol_table: Mutex::new(None),
+                data_type_table: Mutex::new(None),
+            }),
         }
     }
 
     /// Connect to host and start reader thread.
     /// Fails if host is not reachable or if the reader thread can't be started.
-    pub fn connect(&mut self) -> ClientResult<ReadStateResponse> {
-        if self.stream.is_none() {
-            self.stream = Some(self.create_stream()?);
-            if self.route.is_none() {
-                self.open_local_port()?;
-            }
-        }
+    /// Without a reconnect policy the var handles and notifications of a lost connection are dropped,
+    /// so connecting again starts a new session.
+    pub fn connect(&self) -> ClientResult<ReadStateResponse> {
+        self.set_connection_state(ConnectionState::Connecting);
+        let result = self.open_connection();
+        self.set_connection_state(match result {
+            Ok(_) => ConnectionState::Connected,
+            Err(_) => ConnectionState::Disconnected,
+        });
+        result
+    }
 
-        if let Some(stream) = &self.stream {
-            if self.route.is_some() {
-                self.ams_source_address
-                    .update_from_socket_addr(stream.local_addr()?)?;
+    /// Create the TCP stream, start or update the reader thread and check if the host is responding
+    fn open_connection(&self) -> ClientResult<ReadStateResponse> {
+        let mut new_stream = false;
+        {
+            let mut connection = lock(&self.inner.connection);
+            match self.reader_health().state {
+                //The reader thread lost the connection before the client noticed it
+                ReaderState::ConnectionLost => {
+                    if let Some(stream) = connection.stream.take() {
+                        let _ = stream.shutdown(Shutdown::Both);
+                    }
+                }
+                ReaderState::Stopped => connection.thread_started = false,
+                _ => (),
+            }
+            if connection.stream.is_none() {
+                let stream = self.create_stream()?;
+                if self.inner.route.is_none() {
+                    self.open_local_port(&stream)?;
+                }
+                connection.stream = Some(stream);
+                new_stream = true;
             }
 
-            if !self.thread_started {
-                let (tx, rx) = channel::<(u32, Sender<ClientResult<Response>>)>();
-                let (tx_not, rx_not) =
-                    channel::<(u32, Sender<ClientResult<(AdsNotificationSample, TimeStamp)>>)>();
-                let (tx_tcp, rx_tcp) = channel::<TcpStream>();
-                self.tx_general = Some(tx);
-                self.tx_notification = Some(tx_not);
-                self.tx_stream_update = Some(tx_tcp);
-                self.thread_started = run_reader_thread(stream.try_clone()?, rx, rx_not, rx_tcp)?;
-            } else if let Some(tx) = &self.tx_stream_update {
-                tx.send(stream.try_clone()?)?;
+            let connection = &mut *connection;
+            if let Some(stream) = &connection.stream {
+                if self.inner.route.is_some() {
+                    lock(&self.inner.ams_source_address)
+                        .update_from_socket_addr(stream.local_addr()?)?;
+                }
+
+                if !connection.thread_started {
+                    let (tx, rx) = channel::<(u32, Sender<ClientResult<Response>>)>();
+                    let (tx_not, rx_not) = channel::<NotificationUpdate>();
+                    let (tx_tcp, rx_tcp) = channel::<TcpStream>();
+                    let (tx_cancel, rx_cancel) = channel::<u32>();
+                    let (tx_lost, rx_lost) = channel::<SessionEvent>();
+                    connection.tx_general = Some(tx);
+                    connection.tx_session = Some(tx_lost.clone());
+                    connection.tx_notification = Some(tx_not);
+                    connection.tx_stream_update = Some(tx_tcp);
+                    connection.tx_cancel = Some(tx_cancel);
+                    connection.thread_started = run_reader_thread(
+                        stream.try_clone()?,
+                        rx,
+                        rx_not,
+                        rx_tcp,
+                        rx_cancel,
+                        tx_lost,
+                        self.inner.reader_health.clone(),
+                    )?;
+                    run_reconnect_thread(self, rx_lost)?;
+                } else if let Some(tx) = &connection.tx_stream_update {
+                    tx.send(stream.try_clone()?)
+                        .map_err(|_| ClientError::NotConnected)?;
+                }
+            } else {
+                return Err(ClientError::NotConnected);
             }
-            //Check if host is responding
-            self.read_state()
-        } else {
-            Err(anyhow!(AdsError::ErrPortNotConnected))
         }
+        //Check if host is responding
+        let state = self.read_state()?;
+        if new_stream {
+            //Not every port has a symbol table (e.g. system service). Nothing to invalidate then.
+            let _ = self.watch_symbol_version();
+        }
+        Ok(state)
     }
 
     /// Create the TCP stream
-    fn create_stream(&mut self) -> ClientResult<TcpStream> {
-        let mut route = Ipv4Addr::from_str("127.0.0.1")?;
-        if let Some(r) = self.route {
-            route = r;
-        }
+    fn create_stream(&self) -> ClientResult<TcpStream> {
+        let route = self.inner.route.unwrap_or(Ipv4Addr::LOCALHOST);
 
         let stream = TcpStream::connect(SocketAddr::from((route, ADS_TCP_SERVER_PORT)))?;
         stream.set_nodelay(true)?;
@@ -128,53 +306,349 @@ impl Client {
     }
 
     /// open local port in case of local machine
-    fn open_local_port(&mut self) -> ClientResult<()> {
-        let request_port_msg = [0, 16, 2, 0, 0, 0, 0, 0];
-        let mut buf = [0; 14];
-
-        if let Some(s) = &mut self.stream {
-            s.write_all(&request_port_msg).unwrap();
-            use std::io::Read;
-            s.read_exact(&mut buf)?;
-            let (_, mut buf_split) = buf.split_at(6);
-            let ams_address = AmsAddress::read_from(&mut buf_split);
-            self.ams_source_address = ams_address.unwrap();
+    fn open_local_port(&self, stream: &TcpStream) -> ClientResult<()> {
+        let mut buf = [0; AMS_PORT_CONNECT_RESPONSE_LEN];
+        let mut s = stream;
+        s.write_all(&AMS_PORT_CONNECT_REQUEST)?;
+        use std::io::Read;
+        s.read_exact(&mut buf)?;
+        *lock(&self.inner.ams_source_address) = local_port_address(&buf)?;
+        Ok(())
+    }
+
+    /// Re-establish the TCP connection.
+    /// Var handles are requested again and every active device notification is added again on the host,
+    /// so the receivers returned by add_device_notification keep receiving samples.
+    /// The reconnect thread does the same if a reconnect policy is set.
+    pub fn reconnect(&self) -> ClientResult<ReadStateResponse> {
+        self.set_connection_state(ConnectionState::Reconnecting);
+        let result = self.restore_connection();
+        self.set_connection_state(match result {
+            Ok(_) => ConnectionState::Connected,
+            Err(_) => ConnectionState::Disconnected,
+        });
+        result
+    }
+
+    /// Replace the TCP stream and restore var handles and device notifications
+    pub(crate) fn restore_connection(&self) -> ClientResult<ReadStateResponse> {
+        if let Some(stream) = lock(&self.inner.connection).stream.take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        let state = self.open_connection()?;
+        self.restore_var_handles(false)?;
+        self.restore_notifications(false)?;
+        Ok(state)
+    }
+
+    /// Request the handles of all cached var names again.
+    /// With release_old the old handles are released on the host first (errors are ignored).
+    fn restore_var_handles(&self, release_old: bool) -> ClientResult<()> {
+        let old_handles: Vec<(String, u32)> = lock(&self.inner.handle_list).drain().collect();
+        self.bump_handle_generation();
+        let var_names: Vec<String> = old_handles.iter().map(|(name, _)| name.clone()).collect();
+        if release_old {
+            let _ = self.sumup_release_handles(old_handles);
+        }
+        if !var_names.is_empty() {
+            self.sumup_get_var_handle(&var_names)?;
+        }
+        Ok(())
+    }
+
+    /// Add all active device notifications again. The existing receivers keep receiving samples.
+    /// With delete_old the old notifications are deleted on the host first (errors are ignored).
+    fn restore_notifications(&self, delete_old: bool) -> ClientResult<()> {
+        let notifications: Vec<(u64, ActiveNotification)> = lock(&self.inner.notifications)
+            .iter()
+            .map(|(id, n)| (*id, n.clone()))
+            .collect();
+        for (id, notification) in notifications {
+            if delete_old {
+                let _ = self.request(Request::DeleteDeviceNotification(
+                    request_factory::get_delete_device_notification(notification.handle),
+                ));
+                self.remove_notification_sink(notification.handle);
+            }
+            let tx = notification.tx.clone();
+            match self.register_notification(id, notification) {
+                Ok(()) => (),
+                //The symbol is gone (e.g. changed PLC program). Inform the receiver and forget the notification.
+                Err(e @ ClientError::Ads(_)) | Err(e @ ClientError::SymbolNotFound { .. }) => {
+                    lock(&self.inner.notifications).remove(&id);
+                    tx.send(Err(e));
+                    tx.close();
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Watch the symbol version of the target with an on change device notification.
+    /// The notification is added once, restored like the other notifications and deleted with the client.
+    fn watch_symbol_version(&self) -> ClientResult<()> {
+        let active = lock(&self.inner.symbol_version_watch)
+            .as_ref()
+            .and_then(|token| token.id())
+            .is_some_and(|id| lock(&self.inner.notifications).contains_key(&id));
+        if active {
+            return Ok(());
         }
+
+        let client = self.downgrade();
+        let token = self.add_callback_notification(
+            NotificationTarget::Address {
+                index_group: request_factory::ADSIGRP_SYM_VERSION,
+                index_offset: 0,
+            },
+            1,
+            NotificationSettings::on_change(),
+            Box::new(move |sample| {
+                let version = match sample {
+                    Ok((sample, _)) => sample.data.first().copied(),
+                    Err(_) => None,
+                };
+                if let (Some(version), Some(client)) = (version, client.upgrade()) {
+                    client.update_symbol_version(version);
+                }
+            }),
+        )?;
+        *lock(&self.inner.symbol_version_watch) = Some(token);
         Ok(())
     }
 
+    /// Store the symbol version. Runs on the dispatcher thread, so a change is only passed to the reconnect thread
+    /// which requests the handles again (see symbol_version_changed).
+    fn update_symbol_version(&self, version: u8) {
+        let previous = lock(&self.inner.symbol_version).replace(version);
+        if previous.is_none() || previous == Some(version) {
+            return;
+        }
+        if let Some(tx) = &lock(&self.inner.connection).tx_session {
+            let _ = tx.send(SessionEvent::SymbolVersionChanged(version));
+        }
+    }
+
+    /// A new PLC program has been loaded. The handle cache and the symbol and data type tables are invalidated,
+    /// handles are requested again and notifications are added again for the new PLC program.
+    /// Failures of single notifications are reported on their receivers, other failures as RestoreFailed event.
+    pub(crate) fn symbol_version_changed(&self, version: u8) {
+        *lock(&self.inner.symbol_table) = None;
+        *lock(&self.inner.data_type_table) = None;
+        let result = self
+            .restore_var_handles(true)
+            .and_then(|_| self.restore_notifications(true));
+        let mut monitor = lock(&self.inner.connection_monitor);
+        if let Err(e) = result {
+            monitor.restore_failed(&e);
+        }
+        monitor.symbol_version_changed(version);
+    }
+
+    /// Set the reconnect policy. With a policy the client reconnects automatically after the connection has been lost.
+    /// None (default) disables automatic reconnects.
+    pub fn set_reconnect_policy(&self, policy: Option<ReconnectPolicy>) {
+        *lock(&self.inner.reconnect_policy) = policy;
+    }
+
+    /// Current reconnect policy
+    pub fn reconnect_policy(&self) -> Option<ReconnectPolicy> {
+        lock(&self.inner.reconnect_policy).clone()
+    }
+
+    /// Current state of the connection
+    pub fn connection_state(&self) -> ConnectionState {
+        lock(&self.inner.connection_monitor).state()
+    }
+
+    /// Receiver of connection state changes and ADS state changes (Run, Stop...) of the target.
+    /// The current connection state is received first. ADS state changes are detected by read_state (also called by connect) and watch_state.
+    pub fn subscribe_connection_events(&self) -> Receiver<ConnectionEvent> {
+        lock(&self.inner.connection_monitor).subscribe()
+    }
+
+    pub(crate) fn set_connection_state(&self, state: ConnectionState) {
+        lock(&self.inner.connection_monitor).set_state(state);
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakClient {
+        WeakClient {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    /// Status of the reader thread (state, number of undecodable packets and the last error)
+    pub fn reader_health(&self) -> ReaderHealth {
+        lock(&self.inner.reader_health).clone()
+    }
+
+    /// Set the time to wait for a response. Used by request and all methods built on it.
+    /// Default is DEFAULT_REQUEST_TIMEOUT
+    pub fn set_timeout(&self, timeout: Duration) {
+        *lock(&self.inner.timeout) = timeout;
+    }
+
+    /// Time to wait for a response
+    pub fn timeout(&self) -> Duration {
+        *lock(&self.inner.timeout)
+    }
+
     /// Sends the supplied request
-    /// Blocks until the response has been received or on error occures
+    /// Blocks until the response has been received, an error occures or the timeout (see set_timeout) elapsed
     /// Fails if no tcp stream is available.
-    pub fn request(&mut self, request: Request) -> ClientResult<Response> {
-        let rx = self.request_rx(request)?;
-        let response = rx.recv()?;
+    pub fn request(&self, request: Request) -> ClientResult<Response> {
+        self.request_timeout(request, self.timeout())
+    }
+
+    /// Sends the supplied request and waits at most `timeout` for the response.
+    /// Fails with ClientError::Timeout if no response has been received in time.
+    pub fn request_timeout(&self, request: Request, timeout: Duration) -> ClientResult<Response> {
+        let (invoke_id, rx) = self.send_request(request)?;
+        self.wait_response(invoke_id, rx, timeout)
+    }
+
+    /// Send all requests before waiting for the first response. All responses have to arrive within the timeout.
+    /// Returns the responses in the order of the requests. Fails with the first failed request,
+    /// the requests still waiting for a response are cancelled then.
+    fn request_pipelined(&self, requests: Vec<Request>) -> ClientResult<Vec<Response>> {
+        let deadline = Instant::now() + self.timeout();
+        let mut pending = Vec::with_capacity(requests.len());
+        for request in requests {
+            match self.send_request(request) {
+                Ok(sent) => pending.push(sent),
+                Err(e) => {
+                    self.cancel_requests(pending.iter().map(|(invoke_id, _)| *invoke_id));
+                    return Err(e);
+                }
+            }
+        }
+        self.wait_pipelined(pending, deadline)
+    }
+
+    /// Send encoded requests (see request_frame) like request_pipelined.
+    /// The invoke id of the frames is updated in place, nothing is encoded again.
+    fn frames_pipelined(&self, frames: &mut [Vec<u8>]) -> ClientResult<Vec<Response>> {
+        let deadline = Instant::now() + self.timeout();
+        let mut pending = Vec::with_capacity(frames.len());
+        for frame in frames {
+            let invoke_id = self.next_invoke_id();
+            let sent = set_frame_invoke_id(frame, invoke_id)
+                .and_then(|_| self.send_frame(invoke_id, frame));
+            match sent {
+                Ok(sent) => pending.push(sent),
+                Err(e) => {
+                    self.cancel_requests(pending.iter().map(|(invoke_id, _)| *invoke_id));
+                    return Err(e);
+                }
+            }
+        }
+        self.wait_pipelined(pending, deadline)
+    }
+
+    /// Wait for the responses of pipelined requests until the deadline.
+    /// Fails with the first failed request, the requests still waiting for a response are cancelled then.
+    fn wait_pipelined(
+        &self,
+        pending: Vec<(u32, Receiver<ClientResult<Response>>)>,
+        deadline: Instant,
+    ) -> ClientResult<Vec<Response>> {
+        let timeout = self.timeout();
+        let mut responses = Vec::with_capacity(pending.len());
+        let mut pending = pending.into_iter();
+        while let Some((invoke_id, rx)) = pending.next() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.wait_response(invoke_id, rx, remaining) {
+                Ok(response) => responses.push(response),
+                Err(e) => {
+                    self.cancel_requests(pending.map(|(invoke_id, _)| invoke_id));
+                    return Err(match e {
+                        ClientError::Timeout { invoke_id, .. } => {
+                            ClientError::Timeout { invoke_id, timeout }
+                        }
+                        e => e,
+                    });
+                }
+            }
+        }
+        Ok(responses)
+    }
+
+    /// Remove the orphaned senders of requests which are not waited for any more from the reader thread
+    fn cancel_requests(&self, invoke_ids: impl Iterator<Item = u32>) {
+        if let Some(tx) = &lock(&self.inner.connection).tx_cancel {
+            for invoke_id in invoke_ids {
+                let _ = tx.send(invoke_id);
+            }
+        }
+    }
+
+    /// Wait for the response to the request with invoke_id
+    fn wait_response(
+        &self,
+        invoke_id: u32,
+        rx: Receiver<ClientResult<Response>>,
+        timeout: Duration,
+    ) -> ClientResult<Response> {
+        let response = match rx.recv_timeout(timeout) {
+            Ok(response) => response,
+            Err(RecvTimeoutError::Timeout) => {
+                self.cancel_requests(std::iter::once(invoke_id));
+                return Err(ClientError::Timeout { invoke_id, timeout });
+            }
+            //The reader thread has ended
+            Err(_) => return Err(ClientError::NotConnected),
+        };
         self.check_tcp_stream(&response);
         response
     }
 
     /// Sends a request and returns imediatly a receiver object to read from (mpsc::Receiver).
     /// Fails if no tcp stream is available.
-    pub fn request_rx(&mut self, request: Request) -> ClientResult<Receiver<Result<Response>>> {
-        let ams_header = self.new_tcp_ams_request_header(request);
-        let (tx, rx) = channel::<ClientResult<Response>>();
-        self.get_general_tx()?
-            .send((self.invoke_id, tx))
-            .expect("Failed to send request to thread by mpsc channel");
-        let mut buffer = Vec::new();
+    pub fn request_rx(&self, request: Request) -> ClientResult<Receiver<ClientResult<Response>>> {
+        let (_, rx) = self.send_request(request)?;
+        Ok(rx)
+    }
 
-        ams_header.write_to(&mut buffer)?;
+    /// Register a receiver for the response at the reader thread and write the request
+    /// Returns the invoke id and the receiver
+    fn send_request(
+        &self,
+        request: Request,
+    ) -> ClientResult<(u32, Receiver<ClientResult<Response>>)> {
+        let invoke_id = self.next_invoke_id();
+        let buffer = request_frame(
+            &self.inner.ams_targed_address,
+            &lock(&self.inner.ams_source_address),
+            invoke_id,
+            request,
+        )?;
+        self.send_frame(invoke_id, &buffer)
+    }
+
+    /// Register a receiver for the response at the reader thread and write the encoded request
+    fn send_frame(
+        &self,
+        invoke_id: u32,
+        buffer: &[u8],
+    ) -> ClientResult<(u32, Receiver<ClientResult<Response>>)> {
+        let (tx, rx) = channel::<ClientResult<Response>>();
+        let mut connection = lock(&self.inner.connection);
+        connection
+            .get_general_tx()?
+            .send((invoke_id, tx))
+            .map_err(|_| ClientError::NotConnected)?;
 
-        if let Some(s) = &mut self.stream {
-            s.write_all(&buffer)?;
-            return Ok(rx);
+        if let Some(s) = &mut connection.stream {
+            s.write_all(buffer)?;
+            return Ok((invoke_id, rx));
         }
-        Err(anyhow!(AdsError::AdsErrClientPortNotOpen))
+        Err(ClientError::NotConnected)
     }
 
     /// Read a var value by it's name.
     /// Returns ReadResponse
-    pub fn read_by_name(&mut self, var_name: &str, len: u32) -> ClientResult<ReadResponse> {
+    pub fn read_by_name(&self, var_name: &str, len: u32) -> ClientResult<ReadResponse> {
         let handle = self.get_var_handle(var_name)?;
         let request = Request::Read(request_factory::get_read_request(handle, len));
         let response = self.request(request)?;
@@ -182,64 +656,74 @@ impl Client {
         Ok(read_response)
     }
 
+    /// Read a var value by it's name and decode it into T.
+    /// The read length is taken from T. Fails if the host returns an error.
+    pub fn read_value<T: AdsType>(&self, var_name: &str) -> ClientResult<T> {
+        let response = self.read_by_name(var_name, T::SIZE as u32)?;
+        if response.result != AdsError::ErrNoError {
+            return Err(ClientError::Ads(response.result));
+        }
+        T::decode(&response.data)
+    }
+
     /// Read a list of var values by name. This will bundle all requested variables into a single request.
-    /// Returns a HashMap<String, ReadResponse>
+    /// Returns the data or the error of every var in the order of var_list.
+    /// A var fails if its handle could not be requested or if the host returned an error for the read.
     pub fn sumup_read_by_name(
-        &mut self,
-        var_list: &HashMap<String, u32>,
-    ) -> ClientResult<HashMap<String, ReadResponse>> {
-        let mut requests: Vec<ReadRequest> = Vec::new();
-        let mut var_names: Vec<String> = Vec::new();
-        for name in var_list.keys() {
-            var_names.push(name.clone());
-        }
-
-        let handles = self.sumup_get_var_handle(&var_names)?;      
-        let mut failed: Vec<String> = Vec::new();  
-        for (var, length) in var_list {
-            if let Some(h) = handles.get(var) {
-                if let Some(h) = h {
-                    requests.push(get_read_request(*h, *length));
-                } else {
-                    failed.push(var.clone());
-                }              
-            }
-        }
+        &self,
+        var_list: &[(&str, u32)],
+    ) -> ClientResult<SumupResult<Vec<u8>>> {
+        let var_names: Vec<String> = var_list.iter().map(|(name, _)| name.to_string()).collect();
+        let handles = self.sumup_get_var_handle(&var_names)?;
 
-        let mut var_list = var_list.clone();
-        for key in failed {
-            var_list.remove(&key);
+        let (mut results, requested, requests) =
+            sumup_by_name(var_list, &handles, Vec::new(), |handle, length| {
+                get_read_request(handle, *length)
+            })?;
+        if requests.is_empty() {
+            return Ok(results);
         }
-        
-        let mut buf = Vec::new();
-        let sumup_request = SumupReadRequest::new(requests);
-        sumup_request.write_to(&mut buf)?;        
-        let request = Request::ReadWrite(get_sumup_read_request(
-            sumup_request.request_count(),
-            sumup_request.expected_response_len(),
-            buf,
-        ));
-        let response = self.request(request)?;
-        let read_write_response: ReadWriteResponse = response.try_into()?;
-        let sumup_read_response =
-            SumupReadResponse::read_from(&mut read_write_response.data.as_slice())?;
-        let mut result: HashMap<String, ReadResponse> = HashMap::new();
-
-        if read_write_response.result == AdsError::ErrNoError {
-            for (n, (name, _)) in var_list.iter().enumerate() {
-                if n < sumup_read_response.read_responses.len() {
-                    result.insert(name.clone(), sumup_read_response.read_responses[n].clone());
-                }
-            }
-        } else {
-            return Err(anyhow![read_write_response.result]);
+
+        let (sumup_requests, counts): (Vec<Request>, Vec<usize>) =
+            sumup_read_chunks(&requests)?.into_iter().unzip();
+        let responses = self.request_pipelined(sumup_requests)?;
+        store_sumup_read_responses(&mut results, requested, responses, counts)?;
+        Ok(results)
+    }
+
+    /// Prepare a read group for a list of vars (name, length).
+    /// The handles are requested and the sumup requests are built once. Read the group with read_group.
+    pub fn prepare_read_group(&self, var_list: &[(&str, u32)]) -> ClientResult<ReadGroup> {
+        let mut group = ReadGroup::new(var_list);
+        self.prepare_group(&mut group)?;
+        Ok(group)
+    }
+
+    /// Read all vars of a read group with the prepared sumup requests.
+    /// The values and the errors of single vars are stored in the group.
+    /// The requests are built again if the cached handles changed (reconnect, new PLC program).
+    pub fn read_group(&self, group: &mut ReadGroup) -> ClientResult<()> {
+        let generation = self.inner.handle_generation.load(Ordering::Relaxed);
+        if !group.is_prepared(generation, &lock(&self.inner.ams_source_address)) {
+            self.prepare_group(group)?;
         }
-        Ok(result)
+        let responses = self.frames_pipelined(group.frames())?;
+        group.store(responses)
+    }
+
+    fn prepare_group(&self, group: &mut ReadGroup) -> ClientResult<()> {
+        //Read the generation first. A change while the handles are requested is detected by the next read.
+        let generation = self.inner.handle_generation.load(Ordering::Relaxed);
+        let handles = self.sumup_get_var_handle(&group.var_names())?;
+        let source = lock(&self.inner.ams_source_address).clone();
+        group.prepare(&handles, generation, &source, |request| {
+            request_frame(&self.inner.ams_targed_address, &source, 0, request)
+        })
     }
 
     /// Write by name
     /// Returns WriteResponse
-    pub fn write_by_name(&mut self, var_name: &str, data: Vec<u8>) -> ClientResult<WriteResponse> {
+    pub fn write_by_name(&self, var_name: &str, data: Vec<u8>) -> ClientResult<WriteResponse> {
         let handle = self.get_var_handle(var_name)?;
         let request = Request::Write(request_factory::get_write_request(handle, data));
         let response = self.request(request)?;
@@ -247,48 +731,44 @@ impl Client {
         Ok(write_response)
     }
 
+    /// Write a value of type T by name.
+    /// The data length is taken from T. Fails if the host returns an error.
+    pub fn write_value<T: AdsType>(&self, var_name: &str, value: &T) -> ClientResult<()> {
+        let response = self.write_by_name(var_name, value.to_bytes()?)?;
+        if response.result != AdsError::ErrNoError {
+            return Err(ClientError::Ads(response.result));
+        }
+        Ok(())
+    }
+
     /// Write a list of var values by name. This will bundle all the write data into a single write request.
-    /// Returns a HashMap<String, WriteResponse>
+    /// Returns the result of every var in the order of var_list.
+    /// A var fails if its handle could not be requested or if the host returned an error for the write.
     pub fn sumup_write_by_name(
-        &mut self,
-        var_list: HashMap<String, Vec<u8>>,
-    ) -> ClientResult<HashMap<String, WriteResponse>> {
-        let mut requests: Vec<WriteRequest> = Vec::new();
-        for (varname, data) in &var_list {
-            let handle = self.get_var_handle(varname.as_str())?;
-            requests.push(get_write_request(handle, data.clone()));
-        }
-
-        let mut buf = Vec::new();
-        let sumup_request = SumupWriteRequest::new(requests);
-        sumup_request.write_to(&mut buf)?;
-        let request = Request::ReadWrite(get_sumup_write_request(
-            sumup_request.request_count(),
-            sumup_request.expected_response_len(),
-            buf,
-        ));
-        let response = self.request(request)?;
-        let read_write_response: ReadWriteResponse = response.try_into()?;
-        let sumup_write_response =
-            SumupWriteResponse::read_from(&mut read_write_response.data.as_slice())?;
-        let mut result: HashMap<String, WriteResponse> = HashMap::new();
-
-        if read_write_response.result == AdsError::ErrNoError {
-            for (n, (name, _)) in var_list.iter().enumerate() {
-                result.insert(
-                    name.clone(),
-                    sumup_write_response.write_responses[n].clone(),
-                );
-            }
-        } else {
-            return Err(anyhow![read_write_response.result]);
+        &self,
+        var_list: &[(&str, Vec<u8>)],
+    ) -> ClientResult<SumupResult<()>> {
+        let var_names: Vec<String> = var_list.iter().map(|(name, _)| name.to_string()).collect();
+        let handles = self.sumup_get_var_handle(&var_names)?;
+
+        let (mut results, requested, requests) =
+            sumup_by_name(var_list, &handles, (), |handle, data| {
+                get_write_request(handle, data.clone())
+            })?;
+        if requests.is_empty() {
+            return Ok(results);
         }
-        Ok(result)
+
+        let (sumup_requests, counts): (Vec<Request>, Vec<usize>) =
+            sumup_write_chunks(&requests)?.into_iter().unzip();
+        let responses = self.request_pipelined(sumup_requests)?;
+        store_sumup_write_responses(&mut results, requested, responses, counts)?;
+        Ok(results)
     }
 
     /// Read device info
     /// Returns ReadDeviceInfoResponse
-    pub fn read_device_info(&mut self) -> ClientResult<ReadDeviceInfoResponse> {
+    pub fn read_device_info(&self) -> ClientResult<ReadDeviceInfoResponse> {
         let request = Request::ReadDeviceInfo(ReadDeviceInfoRequest::new());
         let response = self.request(request)?;
         let device_info_response: ReadDeviceInfoResponse = response.try_into()?;
@@ -297,17 +777,21 @@ impl Client {
 
     /// Read PLC state
     /// Returns ReadStateResponse
-    pub fn read_state(&mut self) -> ClientResult<ReadStateResponse> {
+    pub fn read_state(&self) -> ClientResult<ReadStateResponse> {
         let request = Request::ReadState(ReadStateRequest::new());
         let response = self.request(request)?;
         let device_state: ReadStateResponse = response.try_into()?;
+        if device_state.result == AdsError::ErrNoError {
+            lock(&self.inner.connection_monitor)
+                .set_ads_state(device_state.ads_state, device_state.device_state);
+        }
         Ok(device_state)
     }
 
     /// Write control
     /// Returns WriteControlResponse
     pub fn write_control(
-        &mut self,
+        &self,
         ads_state: AdsState,
         device_state: u16,
     ) -> ClientResult<WriteControlResponse> {
@@ -323,7 +807,7 @@ impl Client {
     /// Read and write data
     /// Returns ReadWriteResponse
     pub fn read_write(
-        &mut self,
+        &self,
         index_offset: u32,
         read_len: u32,
         write_data: Vec<u8>,
@@ -338,103 +822,617 @@ impl Client {
         Ok(read_write_response)
     }
 
+    /// Read the symbol upload info (number of symbols and data types and the size of their tables)
+    /// Returns SymbolUploadInfo
+    pub fn read_symbol_upload_info(&self) -> ClientResult<SymbolUploadInfo> {
+        let request = Request::Read(request_factory::get_symbol_upload_info_request());
+        let response: ReadResponse = self.request(request)?.try_into()?;
+        if response.result != AdsError::ErrNoError {
+            return Err(ClientError::Ads(response.result));
+        }
+        SymbolUploadInfo::parse(&response.data)
+    }
+
+    /// Upload the symbol table of the target
+    /// Returns SymbolTable with name, index group/offset, size, type name, flags and comment for every symbol
+    /// The table is also kept by the client for read_dynamic/write_dynamic.
+    pub fn upload_symbols(&self) -> ClientResult<SymbolTable> {
+        let info = self.read_symbol_upload_info()?;
+        let request = Request::Read(request_factory::get_symbol_upload_request(info.symbol_size));
+        let response: ReadResponse = self.request(request)?.try_into()?;
+        if response.result != AdsError::ErrNoError {
+            return Err(ClientError::Ads(response.result));
+        }
+        let symbol_table = SymbolTable::parse(&response.data)?;
+        *lock(&self.inner.symbol_table) = Some(symbol_table.clone());
+        Ok(symbol_table)
+    }
+
+    /// Upload the data type dictionary of the target
+    /// Returns DataTypeTable. Use DataTypeTable::resolve to get the full description (struct members, array bounds, enum values...) of a type
+    /// The table is also kept by the client for read_dynamic/write_dynamic.
+    pub fn upload_data_types(&self) -> ClientResult<DataTypeTable> {
+        let info = self.read_symbol_upload_info()?;
+        let request = Request::Read(request_factory::get_data_type_upload_request(
+            info.data_type_size,
+        ));
+        let response: ReadResponse = self.request(request)?.try_into()?;
+        if response.result != AdsError::ErrNoError {
+            return Err(ClientError::Ads(response.result));
+        }
+        let data_type_table = DataTypeTable::parse(&response.data)?;
+        *lock(&self.inner.data_type_table) = Some(data_type_table.clone());
+        Ok(data_type_table)
+    }
+
+    /// Get the resolved data type of a variable.
+    /// Symbols and data types are uploaded on first use. Struct members can be adressed with their full path (Main.stAxis.position).
+    pub fn get_data_type(&self, var_name: &str) -> ClientResult<DataType> {
+        if lock(&self.inner.symbol_table).is_none() {
+            self.upload_symbols()?;
+        }
+        if lock(&self.inner.data_type_table).is_none() {
+            self.upload_data_types()?;
+        }
+        let symbol_table = lock(&self.inner.symbol_table);
+        let data_type_table = lock(&self.inner.data_type_table);
+        if let (Some(symbols), Some(data_types)) = (&*symbol_table, &*data_type_table) {
+            return resolve_var_type(symbols, data_types, var_name);
+        }
+        Err(ClientError::SymbolNotFound {
+            name: var_name.to_string(),
+        })
+    }
+
+    /// Read a var value by name and decode it with the uploaded type information.
+    /// Returns PlcValue
+    pub fn read_dynamic(&self, var_name: &str) -> ClientResult<PlcValue> {
+        let data_type = self.get_data_type(var_name)?;
+        let response = self.read_by_name(var_name, data_type.size)?;
+        if response.result != AdsError::ErrNoError {
+            return Err(ClientError::Ads(response.result));
+        }
+        PlcValue::decode(&data_type, &response.data)
+    }
+
+    /// Encode a PlcValue with the uploaded type information and write it by name.
+    pub fn write_dynamic(&self, var_name: &str, value: &PlcValue) -> ClientResult<()> {
+        let data_type = self.get_data_type(var_name)?;
+        let mut data = vec![0; data_type.size as usize];
+        value.encode(&data_type, &mut data)?;
+        let response = self.write_by_name(var_name, data)?;
+        if response.result != AdsError::ErrNoError {
+            return Err(ClientError::Ads(response.result));
+        }
+        Ok(())
+    }
+
     /// Add device notification to receive updated values at value change or at a certain time interfall
-    /// Returns mpsc::receiver which can be polled
+    /// Returns a Subscription which can be polled like a mpsc::receiver.
+    /// The notification is deleted on the host when the subscription is dropped.
+    /// Every call adds a new notification on the host, so a var can be watched by several subscriptions.
+    /// max_delay and cycle_time are sent unchanged in the unit of the target (usually 100ns ticks).
+    /// Use add_device_notification_with_settings to pass them as Duration.
     pub fn add_device_notification(
-        &mut self,
+        &self,
         var_name: &str,
         length: u32,
         transmission_mode: AdsTransMode,
         max_delay: u32,
         cycle_time: u32,
-    ) -> ClientResult<Receiver<Result<(AdsNotificationSample, TimeStamp), Error>>> {
-        let handle = self.get_var_handle(var_name)?;
-        let request = Request::AddDeviceNotification(request_factory::get_add_device_notification(
-            handle,
+    ) -> ClientResult<Subscription> {
+        self.add_notification(
+            NotificationTarget::Symbol(var_name.to_string()),
             length,
             transmission_mode,
             max_delay,
             cycle_time,
-        ));
+        )
+    }
 
-        //Get notification handle
-        let response: AddDeviceNotificationResponse = self.request(request)?.try_into()?;
-        let handle = response.notification_handle;
+    /// Add device notification with NotificationSettings. See add_device_notification
+    /// Fails with ClientError::Decode if max_delay or cycle_time does not fit the time unit of the settings.
+    pub fn add_device_notification_with_settings(
+        &self,
+        var_name: &str,
+        length: u32,
+        settings: NotificationSettings,
+    ) -> ClientResult<Subscription> {
+        self.add_notification(
+            NotificationTarget::Symbol(var_name.to_string()),
+            length,
+            settings.transmission_mode,
+            settings.max_delay_ticks()?,
+            settings.cycle_time_ticks()?,
+        )
+    }
+
+    /// Add device notification for the data at index group and index offset instead of a var.
+    /// See add_device_notification
+    pub fn add_device_notification_by_address(
+        &self,
+        index_group: u32,
+        index_offset: u32,
+        length: u32,
+        settings: NotificationSettings,
+    ) -> ClientResult<Subscription> {
+        self.add_notification(
+            NotificationTarget::Address {
+                index_group,
+                index_offset,
+            },
+            length,
+            settings.transmission_mode,
+            settings.max_delay_ticks()?,
+            settings.cycle_time_ticks()?,
+        )
+    }
+
+    fn add_notification(
+        &self,
+        target: NotificationTarget,
+        length: u32,
+        transmission_mode: AdsTransMode,
+        max_delay: u32,
+        cycle_time: u32,
+    ) -> ClientResult<Subscription> {
         //Create mpsc channel for notifications
         let (tx, rx) = channel::<ClientResult<(AdsNotificationSample, TimeStamp)>>();
+        let id = self.next_notification_id();
+        self.register_notification(
+            id,
+            ActiveNotification {
+                target,
+                handle: 0,
+                length,
+                transmission_mode,
+                max_delay,
+                cycle_time,
+                tx: NotificationSink::Channel(tx),
+            },
+        )?;
+        Ok(Subscription::new(
+            rx,
+            NotificationToken::new(id, self.downgrade()),
+        ))
+    }
+
+    /// Add a device notification whose samples are passed to callback.
+    /// All callbacks of the client run one after another on one dispatcher thread owned by the client,
+    /// so a slow callback delays other callbacks but never the responses to requests.
+    /// A callback which panics is dropped.
+    /// The notification is deleted and the callback dropped when the returned token is dropped.
+    pub fn add_device_notification_with_callback<F>(
+        &self,
+        var_name: &str,
+        length: u32,
+        settings: NotificationSettings,
+        callback: F,
+    ) -> ClientResult<NotificationToken>
+    where
+        F: FnMut(ClientResult<(AdsNotificationSample, TimeStamp)>) + Send + 'static,
+    {
+        self.add_callback_notification(
+            NotificationTarget::Symbol(var_name.to_string()),
+            length,
+            settings,
+            Box::new(callback),
+        )
+    }
+
+    fn add_callback_notification(
+        &self,
+        target: NotificationTarget,
+        length: u32,
+        settings: NotificationSettings,
+        callback: NotificationCallback,
+    ) -> ClientResult<NotificationToken> {
+        //Fail before the callback is passed to the dispatcher thread
+        let max_delay = settings.max_delay_ticks()?;
+        let cycle_time = settings.cycle_time_ticks()?;
+        let tx = lock(&self.inner.dispatcher).add(callback)?;
+        let id = self.next_notification_id();
+        let result = self.register_notification(
+            id,
+            ActiveNotification {
+                target,
+                handle: 0,
+                length,
+                transmission_mode: settings.transmission_mode,
+                max_delay,
+                cycle_time,
+                tx: tx.clone(),
+            },
+        );
+        if result.is_err() {
+            tx.close();
+        }
+        result.map(|_| NotificationToken::new(id, self.downgrade()))
+    }
+
+    /// Add the device notification on the host and pass its sender to the reader thread
+    fn register_notification(
+        &self,
+        id: u64,
+        mut notification: ActiveNotification,
+    ) -> ClientResult<()> {
+        let request = match &notification.target {
+            NotificationTarget::Symbol(var_name) => request_factory::get_add_device_notification(
+                self.get_var_handle(var_name)?,
+                notification.length,
+                notification.transmission_mode,
+                notification.max_delay,
+                notification.cycle_time,
+            ),
+            NotificationTarget::Address {
+                index_group,
+                index_offset,
+            } => request_factory::get_add_device_notification_by_address(
+                *index_group,
+                *index_offset,
+                notification.length,
+                notification.transmission_mode,
+                notification.max_delay,
+                notification.cycle_time,
+            ),
+        };
+        let request = Request::AddDeviceNotification(request);
+
+        notification.handle = notification_handle(self.request(request)?)?;
+        self.activate_notification(id, notification)
+    }
+
+    /// Pass the sender of an added notification to the reader thread and keep the notification for restoring
+    fn activate_notification(&self, id: u64, notification: ActiveNotification) -> ClientResult<()> {
         //Send tx to reader thread
-        self.get_notification_tx()?
-            .send((handle, tx))
-            .expect("Failed to send request to thread by mpsc channel");
+        lock(&self.inner.connection)
+            .get_notification_tx()?
+            .send(NotificationUpdate::Add(
+                notification.handle,
+                notification.tx.clone(),
+            ))
+            .map_err(|_| ClientError::NotConnected)?;
 
-        self.notification_handle_list
-            .insert(var_name.to_string(), handle);
-        Ok(rx)
+        lock(&self.inner.notifications).insert(id, notification);
+        Ok(())
+    }
+
+    /// Id of a new device notification. Unlike the notification handle it does not change on reconnect.
+    fn next_notification_id(&self) -> u64 {
+        self.inner.notification_id.fetch_add(1, Ordering::Relaxed) + 1
     }
 
-    /// Release a device notification on the host
-    /// Returns DeleteDeviceNotificationResponse
+    /// Ids and handles of the active notifications of a var, oldest first
+    fn notifications_of(&self, var_name: &str) -> Vec<(u64, u32)> {
+        let mut notifications: Vec<(u64, u32)> = lock(&self.inner.notifications)
+            .iter()
+            .filter(
+                |(_, n)| matches!(&n.target, NotificationTarget::Symbol(name) if name == var_name),
+            )
+            .map(|(id, n)| (*id, n.handle))
+            .collect();
+        notifications.sort_unstable();
+        notifications
+    }
+
+    /// Forget the notification with id and delete it on the host.
+    /// Returns None if the notification has already been deleted.
+    pub(crate) fn delete_notification(
+        &self,
+        id: u64,
+    ) -> ClientResult<Option<DeleteDeviceNotificationResponse>> {
+        let notification = match lock(&self.inner.notifications).remove(&id) {
+            Some(notification) => notification,
+            None => return Ok(None),
+        };
+        self.close_notification(&notification);
+        let request = Request::DeleteDeviceNotification(
+            request_factory::get_delete_device_notification(notification.handle),
+        );
+        Ok(Some(self.request(request)?.try_into()?))
+    }
+
+    /// Stop forwarding the samples of a deleted notification.
+    /// The reader thread drops its sender, so the receiver of the subscription is disconnected, and a callback is dropped.
+    fn close_notification(&self, notification: &ActiveNotification) {
+        self.remove_notification_sink(notification.handle);
+        notification.tx.close();
+    }
+
+    /// Let the reader thread drop the sender of a notification handle
+    fn remove_notification_sink(&self, handle: u32) {
+        if let Ok(tx) = lock(&self.inner.connection).get_notification_tx() {
+            let _ = tx.send(NotificationUpdate::Remove(handle));
+        }
+    }
+
+    /// Watch the ADS state of the target (Run, Stop, Config...) with an on change device notification.
+    /// Every transition is received as (AdsState, device_state). The current state is received first.
+    /// The transitions are also sent to the receivers of subscribe_connection_events.
+    /// The notification is restored after a reconnect and deleted on the host when the watch is dropped.
+    pub fn watch_state(&self) -> ClientResult<StateWatch> {
+        //The samples are decoded on the dispatcher thread, the reader thread never waits for the monitor
+        let (tx, rx) = channel::<ClientResult<(AdsState, u16)>>();
+        let client = self.downgrade();
+        let token = self.add_callback_notification(
+            NotificationTarget::Address {
+                index_group: request_factory::ADSIGRP_DEVICE_DATA,
+                index_offset: request_factory::ADSIOFFS_DEVDATA_ADSSTATE,
+            },
+            4,
+            NotificationSettings::on_change(),
+            Box::new(move |sample| {
+                let state = sample.and_then(|(sample, _)| decode_ads_state(&sample.data));
+                if let (Ok((ads_state, device_state)), Some(client)) = (&state, client.upgrade()) {
+                    lock(&client.inner.connection_monitor).set_ads_state(*ads_state, *device_state);
+                }
+                let _ = tx.send(state);
+            }),
+        )?;
+        Ok(StateWatch::new(rx, token))
+    }
+
+    /// Subscribe to a var with a device notification.
+    /// The samples are decoded to T (the length is taken from T) and the time stamps converted to SystemTime.
+    /// The notification is deleted on the host when the subscription is dropped.
+    pub fn subscribe<T: AdsType>(
+        &self,
+        var_name: &str,
+        settings: NotificationSettings,
+    ) -> ClientResult<TypedSubscription<T>> {
+        let subscription = self.add_device_notification_with_settings(
+            var_name,
+            u32::try_from(T::SIZE)?,
+            settings,
+        )?;
+        Ok(TypedSubscription::new(subscription))
+    }
+
+    /// Add device notifications for a list of vars (name, length) with sumup requests (ADSIGRP_SUMUP_ADDDEVNOTE).
+    /// Returns the subscription or the error of every var in the order of var_list. See add_device_notification.
+    pub fn sumup_add_device_notifications(
+        &self,
+        var_list: &[(&str, u32)],
+        transmission_mode: AdsTransMode,
+        max_delay: u32,
+        cycle_time: u32,
+    ) -> ClientResult<SumupResult<Subscription>> {
+        let var_names: Vec<String> = var_list.iter().map(|(name, _)| name.to_string()).collect();
+        let handles = self.sumup_get_var_handle(&var_names)?;
+        //Check before anything is added on the host
+        if let Some(name) = var_names.iter().find(|name| !handles.contains_key(*name)) {
+            return Err(ClientError::HandleNotFound { name: name.clone() });
+        }
+
+        let requests = sumup_add_device_notification_requests(
+            var_list,
+            &handles,
+            transmission_mode,
+            max_delay,
+            cycle_time,
+        );
+        let (sumup_requests, counts): (Vec<Request>, Vec<usize>) =
+            sumup_add_device_notification_chunks(&requests)?
+                .into_iter()
+                .unzip();
+        let responses = self.request_pipelined(sumup_requests)?;
+        let outcomes =
+            match sumup_add_device_notification_outcomes(var_list, &handles, responses, counts)? {
+                Ok(outcomes) => outcomes,
+                Err(added) => {
+                    let _ = self.sumup_delete_notification_handles(&added);
+                    return Err(ClientError::ProtocolViolation(
+                        "Sumup add device notification response too short".to_string(),
+                    ));
+                }
+            };
+
+        let mut results = Vec::with_capacity(var_list.len());
+        for (index, ((name, length), outcome)) in var_list.iter().zip(&outcomes).enumerate() {
+            let result = match outcome {
+                Ok((AdsError::ErrNoError, handle)) => {
+                    let (tx, rx) = channel::<ClientResult<(AdsNotificationSample, TimeStamp)>>();
+                    let id = self.next_notification_id();
+                    let activated = self.activate_notification(
+                        id,
+                        ActiveNotification {
+                            target: NotificationTarget::Symbol(name.to_string()),
+                            handle: *handle,
+                            length: *length,
+                            transmission_mode,
+                            max_delay,
+                            cycle_time,
+                            tx: NotificationSink::Channel(tx),
+                        },
+                    );
+                    if let Err(e) = activated {
+                        //The subscriptions in results delete their notifications when dropped.
+                        //Delete the notifications which have no subscription yet.
+                        let pending = added_notification_handles(outcomes[index..].iter());
+                        let _ = self.sumup_delete_notification_handles(&pending);
+                        return Err(e);
+                    }
+                    Ok(Subscription::new(
+                        rx,
+                        NotificationToken::new(id, self.downgrade()),
+                    ))
+                }
+                Ok((error, _)) | Err(error) => Err(error.clone()),
+            };
+            results.push((name.to_string(), result));
+        }
+        Ok(results)
+    }
+
+    /// Delete notifications on the host with sumup requests. Returns the result of every handle.
+    fn sumup_delete_notification_handles(&self, handles: &[u32]) -> ClientResult<Vec<AdsError>> {
+        let (sumup_requests, counts): (Vec<Request>, Vec<usize>) =
+            sumup_delete_device_notification_chunks(handles)?
+                .into_iter()
+                .unzip();
+        sumup_delete_device_notification_results(self.request_pipelined(sumup_requests)?, counts)
+    }
+
+    /// Delete the device notifications of a list of vars with sumup requests (ADSIGRP_SUMUP_DELDEVNOTE).
+    /// All notifications of a var are deleted. Returns the result of the host for every var in the order of var_names
+    /// (the first error if one of the notifications of the var could not be deleted).
+    /// Fails with HandleNotFound without deleting anything if a var has no notification.
+    pub fn sumup_delete_device_notifications(
+        &self,
+        var_names: &[&str],
+    ) -> ClientResult<SumupResult<()>> {
+        let mut var_notifications = Vec::with_capacity(var_names.len());
+        for name in var_names {
+            let notifications = self.notifications_of(name);
+            if notifications.is_empty() {
+                return Err(ClientError::HandleNotFound {
+                    name: name.to_string(),
+                });
+            }
+            var_notifications.push(notifications);
+        }
+        let handles: Vec<u32> = var_notifications
+            .iter()
+            .flatten()
+            .map(|(_, handle)| *handle)
+            .collect();
+
+        let responses = self.sumup_delete_notification_handles(&handles)?;
+
+        let mut responses = responses.into_iter();
+        Ok(var_names
+            .iter()
+            .zip(var_notifications)
+            .map(|(name, var_notifications)| {
+                let mut result = Ok(());
+                for (id, _) in var_notifications {
+                    let notification = lock(&self.inner.notifications).remove(&id);
+                    if let Some(notification) = notification {
+                        self.close_notification(&notification);
+                    }
+                    //The number of responses has been checked by the sumup parser
+                    if let Some(error) = responses.next() {
+                        if error != AdsError::ErrNoError && result.is_ok() {
+                            result = Err(error);
+                        }
+                    }
+                }
+                (name.to_string(), result)
+            })
+            .collect())
+    }
+
+    /// Release the device notifications of a var on the host. All notifications of the var are deleted.
+    /// Returns DeleteDeviceNotificationResponse (the first failed one if the host rejected a deletion)
     pub fn delete_device_notification(
-        &mut self,
+        &self,
         var_name: &str,
     ) -> ClientResult<DeleteDeviceNotificationResponse> {
-        let handle;
-        if let Some(h) = self.notification_handle_list.get(var_name) {
-            handle = *h;
-            let request = Request::DeleteDeviceNotification(
-                request_factory::get_delete_device_notification(handle),
-            );
-            let response = self.request(request)?;
-            let response: DeleteDeviceNotificationResponse = response.try_into()?;
-            self.notification_handle_list.remove(var_name);
-            return Ok(response);
+        let mut result: Option<DeleteDeviceNotificationResponse> = None;
+        for (id, _) in self.notifications_of(var_name) {
+            if let Some(response) = self.delete_notification(id)? {
+                if !matches!(&result, Some(r) if r.result != AdsError::ErrNoError) {
+                    result = Some(response);
+                }
+            }
+        }
+        result.ok_or_else(|| ClientError::HandleNotFound {
+            name: var_name.to_string(),
+        })
+    }
+
+    /// Delete every device notification of the client on the host with sumup requests.
+    /// Subscriptions and tokens of the deleted notifications stop receiving samples.
+    /// Fails with SumupPartial if the host could not delete some notifications.
+    pub fn delete_all_notifications(&self) -> ClientResult<()> {
+        let notifications: Vec<ActiveNotification> = lock(&self.inner.notifications)
+            .drain()
+            .map(|(_, notification)| notification)
+            .collect();
+        if notifications.is_empty() {
+            return Ok(());
+        }
+        let mut handles = Vec::with_capacity(notifications.len());
+        for notification in &notifications {
+            self.close_notification(notification);
+            handles.push(notification.handle);
+        }
+
+        let responses = self.sumup_delete_notification_handles(&handles)?;
+
+        let failed: Vec<(String, AdsError)> = notifications
+            .into_iter()
+            .zip(responses)
+            .filter(|(_, error)| *error != AdsError::ErrNoError)
+            .map(|(notification, error)| (notification.target.to_string(), error))
+            .collect();
+        if !failed.is_empty() {
+            return Err(ClientError::SumupPartial { failed });
         }
-        Err(anyhow!(AdsError::AdsErrDeviceSymbolNotFound)) //??
+        Ok(())
     }
 
     /// Returns the notification handle for a given variable name if available in the client.
+    /// If the var has several notifications the handle of the oldest one is returned.
     /// This methode will not reqeust a handle from the host. If the handle is not available, an error will be returned.
     /// If you want to request a handle from the host, use `add_device_notification`.
     pub fn get_notification_handle(&self, var_name: &str) -> ClientResult<u32> {
-        if let Some(handle) = self.notification_handle_list.get(var_name) {
+        if let Some((_, handle)) = self.notifications_of(var_name).first() {
             return Ok(*handle);
         }
-        Err(anyhow!(AdsError::AdsErrDeviceSymbolNotFound))
+        Err(ClientError::HandleNotFound {
+            name: var_name.to_string(),
+        })
     }
 
     /// Get a var handle by name.
-    /// If the handle is already known, it will be returned from the handle list.   
+    /// If the handle is already known, it will be returned from the handle list.
     /// If the handle is not known, a request will be sent to the host to get the handle.
-    pub fn get_var_handle(&mut self, var_name: &str) -> ClientResult<u32> {
-        if let Some(handle) = self.handle_list.get(var_name) {
+    pub fn get_var_handle(&self, var_name: &str) -> ClientResult<u32> {
+        if let Some(handle) = lock(&self.inner.handle_list).get(var_name) {
             return Ok(*handle);
-        } else {
-            let handle = self.request_var_handle(var_name)?;
-            self.handle_list.insert(var_name.to_string(), handle);
-            return Ok(handle);
         }
+        let handle = self.request_var_handle(var_name)?;
+        Ok(self.cache_var_handle(var_name, handle))
+    }
+
+    /// Store a requested handle in the handle list.
+    /// If another thread stored a handle for the same var in the meantime, the new one is released and the cached one returned.
+    fn cache_var_handle(&self, var_name: &str, handle: u32) -> u32 {
+        let cached = *lock(&self.inner.handle_list)
+            .entry(var_name.to_string())
+            .or_insert(handle);
+        if cached != handle {
+            let _ = self.request(Request::Write(request_factory::get_release_handle_request(
+                handle,
+            )));
+        }
+        cached
     }
 
+    /// Get the var handles for a list of names. Unknown handles are requested with a single sumup request.
+    /// Returns the handle or the error code of the host for every name.
     fn sumup_get_var_handle(
-        &mut self,
+        &self,
         var_names: &Vec<String>,
-    ) -> ClientResult<HashMap<String, Option<u32>>> {
+    ) -> ClientResult<HashMap<String, Result<u32, AdsError>>> {
         let mut do_request: Vec<String> = Vec::new();
-        let mut handles: HashMap<String, Option<u32>> = HashMap::new();
-        for var in var_names {
-            if let Some(handle) = self.handle_list.get(var) {
-                handles.insert(var.clone(), Some(*handle));
-            } else {
-                do_request.push(var.clone());
+        let mut handles: HashMap<String, Result<u32, AdsError>> = HashMap::new();
+        {
+            let handle_list = lock(&self.inner.handle_list);
+            for var in var_names {
+                if let Some(handle) = handle_list.get(var) {
+                    handles.insert(var.clone(), Ok(*handle));
+                } else {
+                    do_request.push(var.clone());
+                }
             }
         }
 
-        if !do_request.is_empty() {            
-            let requested_handles = self.sumup_request_var_handle(&do_request)?;            
+        if !do_request.is_empty() {
+            let requested_handles = self.sumup_request_var_handle(&do_request)?;
             for (name, handle) in requested_handles {
-                if let Some(h) = handle {
-                    self.handle_list.insert(name.clone(), h);
-                }                
+                let handle = handle.map(|h| self.cache_var_handle(&name, h));
                 handles.insert(name, handle);
             }
         }
@@ -442,88 +1440,147 @@ impl Client {
     }
 
     /// Request new var handle
-    fn request_var_handle(&mut self, var_name: &str) -> ClientResult<u32> {
+    fn request_var_handle(&self, var_name: &str) -> ClientResult<u32> {
         let request = Request::ReadWrite(get_var_handle_request(var_name));
-        let response: ReadWriteResponse = self.request(request)?.try_into()?;
+        var_handle(var_name, self.request(request)?)
+    }
 
-        if response.length == 4 {
-            return Ok(response.data.as_slice().read_u32::<LittleEndian>()?);
-        }
-        Err(anyhow!(
-            "Failed to get var handle! Variable {} not found!",
-            var_name
-        ))
+    /// Send ReadWrite requests bundled into sumup requests (ADSIGRP_SUMUP_READWRITE).
+    /// Returns the read data or the error of the host for every request in the order of requests.
+    pub fn sumup_read_write(
+        &self,
+        requests: &[ReadWriteRequest],
+    ) -> ClientResult<Vec<Result<Vec<u8>, AdsError>>> {
+        let (sumup_requests, counts): (Vec<Request>, Vec<usize>) =
+            sumup_read_write_chunks(requests)?.into_iter().unzip();
+        sumup_read_write_results(self.request_pipelined(sumup_requests)?, counts)
     }
 
-    /// Sumup a var handle request
-    /// Not really a sumup request. This methode send for each handle request a tx.
-    // To Do. Is there a way to perform a sumup for handle requests?
+    /// Request the handles of all vars with a single sumup read write request (ADSIGRP_SUMUP_READWRITE)
+    /// Returns the handle or the error code of the host for every var.
     fn sumup_request_var_handle(
-        &mut self,
-        var_list: &Vec<String>,
-    ) -> ClientResult<HashMap<String, Option<u32>>> {
-        let mut result: HashMap<String, Option<u32>> = HashMap::new();
-        for var in var_list {            
-            let response = self.request(Request::ReadWrite(get_var_handle_request(var)))?;
-            let handle: ReadWriteResponse = response.try_into()?;            
-            if handle.result == AdsError::ErrNoError {                
-                result.insert(var.clone(), Some(handle.data.as_slice().read_u32::<LittleEndian>()?));
-            } else {
-                result.insert(var.clone(), None);
-            }                       
+        &self,
+        var_list: &[String],
+    ) -> ClientResult<HashMap<String, Result<u32, AdsError>>> {
+        if var_list.is_empty() {
+            return Ok(HashMap::new());
         }
-        Ok(result)
+        let requests: Vec<ReadWriteRequest> = var_list
+            .iter()
+            .map(|var| get_var_handle_request(var))
+            .collect();
+        sumup_var_handles(var_list, self.sumup_read_write(&requests)?)
+    }
+
+    /// Release the var handles of a list of names with a single sumup request.
+    /// Returns the result of the host for every released handle. Names without handle are ignored.
+    pub fn sumup_release_handle(
+        &self,
+        var_names: &[String],
+    ) -> ClientResult<HashMap<String, AdsError>> {
+        let handles: Vec<(String, u32)> = {
+            let mut handle_list = lock(&self.inner.handle_list);
+            var_names
+                .iter()
+                .filter_map(|name| handle_list.remove(name).map(|h| (name.clone(), h)))
+                .collect()
+        };
+        self.bump_handle_generation();
+        self.sumup_release_handles(handles)
+    }
+
+    /// Release handles with a single sumup write request
+    fn sumup_release_handles(
+        &self,
+        handles: Vec<(String, u32)>,
+    ) -> ClientResult<HashMap<String, AdsError>> {
+        if handles.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let requests: Vec<WriteRequest> = handles
+            .iter()
+            .map(|(_, handle)| request_factory::get_release_handle_request(*handle))
+            .collect();
+        let (sumup_requests, counts): (Vec<Request>, Vec<usize>) =
+            sumup_write_chunks(&requests)?.into_iter().unzip();
+        let mut write_responses = Vec::with_capacity(requests.len());
+        for (response, count) in self
+            .request_pipelined(sumup_requests)?
+            .into_iter()
+            .zip(counts)
+        {
+            write_responses.extend(sumup_write_responses(response, count)?);
+        }
+        Ok(handles
+            .into_iter()
+            .zip(write_responses)
+            .map(|((name, _), response)| (name, response.result))
+            .collect())
     }
 
     /// Release var handle
-    pub fn release_handle(&mut self, var_name: &str) -> ClientResult<WriteResponse> {
-        if let Some(handle) = self.handle_list.get(var_name) {
-            let request = Request::Write(request_factory::get_release_handle_request(*handle));
+    pub fn release_handle(&self, var_name: &str) -> ClientResult<WriteResponse> {
+        let handle = lock(&self.inner.handle_list).remove(var_name);
+        if let Some(handle) = handle {
+            self.bump_handle_generation();
+            let request = Request::Write(request_factory::get_release_handle_request(handle));
             let response = self.request(request)?;
             let response: WriteResponse = response.try_into()?;
-            self.handle_list.remove(var_name);
             return Ok(response);
         }
-        Err(anyhow!("Handle not available"))
+        Err(ClientError::HandleNotFound {
+            name: var_name.to_string(),
+        })
     }
 
-    ///Create new tcp_ams_header with supplied request data.
-    fn new_tcp_ams_request_header(&mut self, request: Request) -> AmsTcpHeader {
-        self.invoke_id += 1;
-        AmsTcpHeader::from(AmsHeader::new(
-            self.ams_targed_address.clone(),
-            self.ams_source_address.clone(),
-            StateFlags::req_default(),
-            self.invoke_id,
-            request,
-        ))
+    /// Next invoke id. Shared by all clones of the client.
+    fn next_invoke_id(&self) -> u32 {
+        self.inner
+            .invoke_id
+            .fetch_add(1, Ordering::Relaxed)
+            .wrapping_add(1)
     }
 
     ///Check if stream disconnected
-    fn check_tcp_stream(&mut self, response: &ClientResult<Response>) {
-        if let Err(e) = response {
-            if e.is::<AdsError>() {
-                let e = e.downcast_ref::<AdsError>();
-                if let Some(e) = e {
-                    if e == &AdsError::ErrPortNotConnected {
-                        if let Some(stream) = &self.stream {
-                            let _ = stream.shutdown(Shutdown::Both);
-                        }
-                        self.handle_list.clear();
-                        self.notification_handle_list.clear();
-                        self.stream = None;
-                    }
-                }
+    fn check_tcp_stream(&self, response: &ClientResult<Response>) {
+        if let Err(ClientError::NotConnected) = response {
+            if let Some(stream) = lock(&self.inner.connection).stream.take() {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+            //With a reconnect policy the handles and notifications are restored by reconnect()
+            if self.reconnect_policy().is_none() {
+                self.forget_session();
             }
         }
     }
 
+    /// The connection is lost and won't be restored. Var handles and notifications of the old
+    /// session are invalid on the host, so connect() starts without them.
+    pub(crate) fn forget_session(&self) {
+        lock(&self.inner.handle_list).clear();
+        let notifications: Vec<ActiveNotification> = lock(&self.inner.notifications)
+            .drain()
+            .map(|(_, notification)| notification)
+            .collect();
+        for notification in &notifications {
+            self.close_notification(notification);
+        }
+        self.bump_handle_generation();
+    }
+
+    /// Cached handles have been released or replaced. Read groups build their requests again.
+    fn bump_handle_generation(&self) {
+        self.inner.handle_generation.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Connection {
     /// Gets the tx (mpsc::sender) to notify the reader thread about a new handle
     fn get_general_tx(&self) -> ClientResult<&TxGeneral> {
         if let Some(tx) = &self.tx_general {
             return Ok(tx);
         }
-        Err(anyhow!(AdsError::AdsErrClientError)) //ToDo create better error
+        Err(ClientError::NotConnected)
     }
 
     /// Gets the tx (mpsc::sender) to notify the reader thread about a new notification handle
@@ -531,6 +1588,347 @@ impl Client {
         if let Some(tx) = &self.tx_notification {
             return Ok(tx);
         }
-        Err(anyhow!(AdsError::AdsErrClientError)) //ToDo create better error
+        Err(ClientError::NotConnected)
+    }
+}
+
+/// Encode a request into an AMS/TCP frame. Shared by Client and AsyncClient.
+pub(crate) fn request_frame(
+    target: &AmsAddress,
+    source: &AmsAddress,
+    invoke_id: u32,
+    request: Request,
+) -> ClientResult<Vec<u8>> {
+    let ams_header = AmsTcpHeader::from(AmsHeader::new(
+        target.clone(),
+        source.clone(),
+        StateFlags::req_default(),
+        invoke_id,
+        request,
+    ));
+    let mut buffer = Vec::new();
+    ams_header.write_to(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// DeleteDeviceNotification requests for the notification handles in one buffer
+pub(crate) fn delete_notification_frames(
+    target: &AmsAddress,
+    source: &AmsAddress,
+    invoke_id: &AtomicU32,
+    handles: impl Iterator<Item = u32>,
+) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    for handle in handles {
+        let request = Request::DeleteDeviceNotification(
+            request_factory::get_delete_device_notification(handle),
+        );
+        let invoke_id = invoke_id.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+        if let Ok(frame) = request_frame(target, source, invoke_id, request) {
+            buffer.extend(frame);
+        }
+    }
+    buffer
+}
+
+/// Set the invoke id of an encoded request (see request_frame)
+fn set_frame_invoke_id(frame: &mut [u8], invoke_id: u32) -> ClientResult<()> {
+    let mut id = frame
+        .get_mut(AMS_TCP_HEADER_SIZE + AMS_HEADER_INVOKE_ID..)
+        .ok_or_else(|| ClientError::Decode("Frame without AMS header".to_string()))?;
+    id.write_u32::<LittleEndian>(invoke_id)?;
+    Ok(())
+}
+
+/// AMS address of the local port opened with AMS_PORT_CONNECT_REQUEST
+pub(crate) fn local_port_address(
+    response: &[u8; AMS_PORT_CONNECT_RESPONSE_LEN],
+) -> ClientResult<AmsAddress> {
+    let (_, mut buf_split) = response.split_at(6);
+    Ok(AmsAddress::read_from(&mut buf_split)?)
+}
+
+/// Var handle from the response to a handle request (see request_factory::get_var_handle_request)
+pub(crate) fn var_handle(var_name: &str, response: Response) -> ClientResult<u32> {
+    let response: ReadWriteResponse = response.try_into()?;
+    if response.result == AdsError::ErrNoError && response.length == 4 {
+        return Ok(response.data.as_slice().read_u32::<LittleEndian>()?);
+    }
+    Err(ClientError::SymbolNotFound {
+        name: var_name.to_string(),
+    })
+}
+
+/// Notification handle from the response to an add device notification request
+pub(crate) fn notification_handle(response: Response) -> ClientResult<u32> {
+    let response: AddDeviceNotificationResponse = response.try_into()?;
+    if response.result != AdsError::ErrNoError {
+        return Err(ClientError::Ads(response.result));
+    }
+    Ok(response.notification_handle)
+}
+
+/// Lock a mutex. A poisoned lock is recovered, the protected data is always left consistent.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Decode the device data sample (ADS state u16, device state u16)
+fn decode_ads_state(data: &[u8]) -> ClientResult<(AdsState, u16)> {
+    if data.len() < 4 {
+        return Err(ClientError::Decode(format!(
+            "ADS state sample too short ({} bytes)",
+            data.len()
+        )));
+    }
+    //Same layout as a read state response without the result code
+    let mut buf = vec![0; 4];
+    buf.extend_from_slice(&data[..4]);
+    let response = ReadStateResponse::read_from(&mut buf.as_slice())?;
+    Ok((response.ads_state, response.device_state))
+}
+
+/// Resolve the type of a symbol or of a struct member path below a symbol
+fn resolve_var_type(
+    symbols: &SymbolTable,
+    data_types: &DataTypeTable,
+    var_name: &str,
+) -> ClientResult<DataType> {
+    if let Some(symbol) = symbols.get(var_name) {
+        let mut data_type = data_types.resolve(&symbol.type_name)?;
+        if let DataTypeKind::Pointer(_) | DataTypeKind::Reference(_) = data_type.kind {
+            data_type.size = symbol.size;
+        }
+        return Ok(data_type);
+    }
+    if let Some((parent, member)) = var_name.rsplit_once('.') {
+        let mut parent_type = resolve_var_type(symbols, data_types, parent)?;
+        while let DataTypeKind::Alias(target) = parent_type.kind {
+            parent_type = *target;
+        }
+        if let DataTypeKind::Struct(members) = parent_type.kind {
+            if let Some(m) = members
+                .into_iter()
+                .find(|m| m.name.eq_ignore_ascii_case(member))
+            {
+                return Ok(m.data_type);
+            }
+        }
+    }
+    Err(ClientError::SymbolNotFound {
+        name: var_name.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::NotificationTimeUnit;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn client() -> Client {
+        Client::new(
+            AmsAddress::new(AmsNetId::from([127, 0, 0, 1, 1, 1]), 851),
+            None,
+        )
+    }
+
+    /// ADS server listening on ip:48898. Answers every request with success and new handles
+    /// and records the command id and the first u32 of the data (index group or handle) of the requests.
+    struct FakeServer {
+        requests: Arc<Mutex<Vec<(u16, u32)>>>,
+        /// Index group and handle of the added notifications
+        notifications: Arc<Mutex<Vec<(u32, u32)>>>,
+        stream: Arc<Mutex<Option<TcpStream>>>,
+    }
+
+    impl FakeServer {
+        fn start(ip: Ipv4Addr) -> Self {
+            let listener = TcpListener::bind((ip, ADS_TCP_SERVER_PORT)).unwrap();
+            let server = FakeServer {
+                requests: Arc::default(),
+                notifications: Arc::default(),
+                stream: Arc::default(),
+            };
+            let requests = server.requests.clone();
+            let notifications = server.notifications.clone();
+            let shared_stream = server.stream.clone();
+            thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                *lock(&shared_stream) = Some(stream.try_clone().unwrap());
+                let mut next_handle = 0;
+                let mut tcp_header = [0; AMS_TCP_HEADER_SIZE];
+                while stream.read_exact(&mut tcp_header).is_ok() {
+                    let length = (&tcp_header[2..]).read_u32::<LittleEndian>().unwrap();
+                    let mut packet = vec![0; length as usize];
+                    stream.read_exact(&mut packet).unwrap();
+                    let command_id = (&packet[16..18]).read_u16::<LittleEndian>().unwrap();
+                    let mut data = &packet[32..];
+                    let index_group = data.read_u32::<LittleEndian>().unwrap_or(0);
+                    lock(&requests).push((command_id, index_group));
+                    let mut handle = || {
+                        next_handle += 1;
+                        next_handle
+                    };
+                    let values: Vec<u32> = match command_id {
+                        //ReadState: result, Run and device state
+                        4 => vec![0, 5],
+                        6 => {
+                            let h = handle();
+                            lock(&notifications).push((index_group, h));
+                            vec![0, h]
+                        }
+                        9 => {
+                            let count = data.read_u32::<LittleEndian>().unwrap();
+                            match index_group {
+                                0xF003 => vec![0, 4, handle()],
+                                //Sumup read write: result and length of every handle, then the handles
+                                0xF082 => {
+                                    let mut values = vec![0, 12 * count];
+                                    values.extend((0..count).flat_map(|_| [0, 4]));
+                                    values.extend((0..count).map(|_| handle()));
+                                    values
+                                }
+                                //Sumup write: result of every sub request
+                                0xF081 => {
+                                    let mut values = vec![0, 4 * count];
+                                    values.extend((0..count).map(|_| 0));
+                                    values
+                                }
+                                _ => vec![0, 0],
+                            }
+                        }
+                        _ => vec![0],
+                    };
+                    let response: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+                    let mut frame = Vec::new();
+                    frame.write_u16::<LittleEndian>(0).unwrap();
+                    frame
+                        .write_u32::<LittleEndian>(32 + response.len() as u32)
+                        .unwrap();
+                    //Swap target and source
+                    frame.extend_from_slice(&packet[8..16]);
+                    frame.extend_from_slice(&packet[0..8]);
+                    frame.write_u16::<LittleEndian>(command_id).unwrap();
+                    frame.write_u16::<LittleEndian>(0x0005).unwrap();
+                    frame
+                        .write_u32::<LittleEndian>(response.len() as u32)
+                        .unwrap();
+                    frame.write_u32::<LittleEndian>(0).unwrap();
+                    frame.extend_from_slice(&packet[28..32]);
+                    frame.extend_from_slice(&response);
+                    stream.write_all(&frame).unwrap();
+                }
+            });
+            server
+        }
+
+        /// Send a device notification with one sample
+        fn notify(&self, handle: u32, sample: &[u8]) {
+            let mut data = Vec::new();
+            data.write_u32::<LittleEndian>(20 + sample.len() as u32)
+                .unwrap();
+            data.write_u32::<LittleEndian>(1).unwrap();
+            data.write_u64::<LittleEndian>(0).unwrap();
+            data.write_u32::<LittleEndian>(1).unwrap();
+            data.write_u32::<LittleEndian>(handle).unwrap();
+            data.write_u32::<LittleEndian>(sample.len() as u32).unwrap();
+            data.extend_from_slice(sample);
+            let mut frame = Vec::new();
+            frame.write_u16::<LittleEndian>(0).unwrap();
+            frame
+                .write_u32::<LittleEndian>(32 + data.len() as u32)
+                .unwrap();
+            frame.extend_from_slice(&[0; 16]);
+            frame.write_u16::<LittleEndian>(8).unwrap();
+            frame.write_u16::<LittleEndian>(0x0004).unwrap();
+            frame.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+            frame.extend_from_slice(&[0; 8]);
+            frame.extend_from_slice(&data);
+            let mut stream = lock(&self.stream);
+            stream.as_mut().unwrap().write_all(&frame).unwrap();
+        }
+
+        /// Number of received requests with command id and index group
+        fn count(&self, command_id: u16, index_group: u32) -> usize {
+            lock(&self.requests)
+                .iter()
+                .filter(|r| **r == (command_id, index_group))
+                .count()
+        }
+
+        /// Handle of the last notification added for index_group
+        fn notification_handle(&self, index_group: u32) -> u32 {
+            lock(&self.notifications)
+                .iter()
+                .rev()
+                .find(|(group, _)| *group == index_group)
+                .map(|(_, handle)| *handle)
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn rejected_callback_settings_do_not_register_the_callback() {
+        let client = client();
+        let owner = Arc::new(());
+        let captured = owner.clone();
+        let settings = NotificationSettings::on_change()
+            .time_unit(NotificationTimeUnit::Ticks100ns)
+            .max_delay(Duration::from_secs(3600));
+        let result =
+            client.add_device_notification_with_callback("MAIN.nCounter", 2, settings, move |_| {
+                let _ = &captured;
+            });
+        assert!(matches!(result, Err(ClientError::Decode(_))));
+        //The callback has been dropped and the dispatcher thread was never started
+        assert_eq!(Arc::strong_count(&owner), 1);
+        assert!(lock(&client.inner.dispatcher).is_idle());
+        assert!(lock(&client.inner.notifications).is_empty());
+    }
+
+    #[test]
+    fn symbol_version_change_restores_the_session() {
+        let ip = Ipv4Addr::new(127, 0, 0, 2);
+        let server = FakeServer::start(ip);
+        let client = Client::new(
+            AmsAddress::new(AmsNetId::from([127, 0, 0, 2, 1, 1]), 851),
+            Some(ip),
+        );
+        let events = client.subscribe_connection_events();
+        client.connect().unwrap();
+        let old_handle = client.get_var_handle("MAIN.nCounter").unwrap();
+        *lock(&client.inner.symbol_table) = Some(SymbolTable::default());
+        *lock(&client.inner.data_type_table) = Some(DataTypeTable::default());
+
+        //The first version is only stored
+        let watch = server.notification_handle(ADSIGRP_SYM_VERSION);
+        server.notify(watch, &[1]);
+        server.notify(watch, &[2]);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let version = loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match events.recv_timeout(timeout).unwrap() {
+                ConnectionEvent::SymbolVersionChanged(version) => break version,
+                ConnectionEvent::RestoreFailed(e) => panic!("Restore failed: {}", e),
+                _ => (),
+            }
+        };
+        assert_eq!(version, 2);
+        assert!(lock(&client.inner.symbol_table).is_none());
+        assert!(lock(&client.inner.data_type_table).is_none());
+        //The old handle has been released and the handle requested again
+        assert_eq!(server.count(9, 0xF081), 1);
+        assert_eq!(server.count(9, 0xF082), 1);
+        let new_handle = *lock(&client.inner.handle_list)
+            .get("MAIN.nCounter")
+            .unwrap();
+        assert_ne!(new_handle, old_handle);
+        //The symbol version watch has been deleted and added again
+        assert_eq!(server.count(7, watch), 1);
+        assert_eq!(server.count(6, ADSIGRP_SYM_VERSION), 2);
     }
 }
@@ -0,0 +1,136 @@
+use crate::client::ClientResult;
+use crate::error::ClientError;
+use crate::notification::NotificationToken;
+use ads_proto::proto::ads_state::AdsState;
+use std::sync::mpsc::{channel, Iter, Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError};
+use std::time::Duration;
+
+/// State of the connection to the target. See Client::connection_state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Not connected (connect() not called yet, failed or the connection has been lost without reconnect policy)
+    #[default]
+    Disconnected,
+    /// connect() is running
+    Connecting,
+    /// The target answered
+    Connected,
+    /// The connection has been lost. The client is reconnecting (see Client::set_reconnect_policy).
+    Reconnecting,
+    /// All reconnect attempts of the reconnect policy failed. Call connect() or reconnect() to try again.
+    Failed,
+}
+
+/// Event sent to the receivers of Client::subscribe_connection_events
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    /// The connection state changed
+    StateChanged(ConnectionState),
+    /// The ADS state of the target changed (e.g. Run -> Stop). Detected by read_state and watch_state.
+    AdsStateChanged {
+        ads_state: AdsState,
+        device_state: u16,
+    },
+    /// A new PLC program has been loaded (download, online change). Contains the new symbol version.
+    /// Cached handles and symbol/data type tables have been invalidated and handles and notifications restored.
+    SymbolVersionChanged(u8),
+    /// Handles or notifications could not be restored after a symbol version change. Contains the error message.
+    RestoreFailed(String),
+}
+
+/// ADS state watch returned by Client::watch_state. Receives every transition as (AdsState, device_state).
+/// The notification is deleted on the host when the watch is dropped.
+#[derive(Debug)]
+pub struct StateWatch {
+    rx: Receiver<ClientResult<(AdsState, u16)>>,
+    token: NotificationToken,
+}
+
+impl StateWatch {
+    pub(crate) fn new(
+        rx: Receiver<ClientResult<(AdsState, u16)>>,
+        token: NotificationToken,
+    ) -> Self {
+        StateWatch { rx, token }
+    }
+
+    /// Wait for the next transition. Fails after the notification has been deleted.
+    pub fn recv(&self) -> Result<ClientResult<(AdsState, u16)>, RecvError> {
+        self.rx.recv()
+    }
+
+    pub fn try_recv(&self) -> Result<ClientResult<(AdsState, u16)>, TryRecvError> {
+        self.rx.try_recv()
+    }
+
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<ClientResult<(AdsState, u16)>, RecvTimeoutError> {
+        self.rx.recv_timeout(timeout)
+    }
+
+    /// Iterate over the transitions. Blocks until the next transition is received.
+    pub fn iter(&self) -> Iter<'_, ClientResult<(AdsState, u16)>> {
+        self.rx.iter()
+    }
+
+    /// Delete the notification on the host and return the error instead of ignoring it like drop
+    pub fn unsubscribe(self) -> ClientResult<()> {
+        self.token.unsubscribe()
+    }
+}
+
+/// Current connection state and the event receivers
+#[derive(Debug, Default)]
+pub(crate) struct ConnectionMonitor {
+    state: ConnectionState,
+    ads_state: Option<(AdsState, u16)>,
+    observers: Vec<Sender<ConnectionEvent>>,
+}
+
+impl ConnectionMonitor {
+    pub(crate) fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Set the state. Observers are only notified if the state changed.
+    pub(crate) fn set_state(&mut self, state: ConnectionState) {
+        if self.state != state {
+            self.state = state;
+            self.emit(ConnectionEvent::StateChanged(state));
+        }
+    }
+
+    /// Set the last ADS state read from the target. Observers are only notified if it changed.
+    pub(crate) fn set_ads_state(&mut self, ads_state: AdsState, device_state: u16) {
+        if self.ads_state != Some((ads_state, device_state)) {
+            self.ads_state = Some((ads_state, device_state));
+            self.emit(ConnectionEvent::AdsStateChanged {
+                ads_state,
+                device_state,
+            });
+        }
+    }
+
+    pub(crate) fn symbol_version_changed(&mut self, version: u8) {
+        self.emit(ConnectionEvent::SymbolVersionChanged(version));
+    }
+
+    pub(crate) fn restore_failed(&mut self, error: &ClientError) {
+        self.emit(ConnectionEvent::RestoreFailed(error.to_string()));
+    }
+
+    /// New observer. The current state is sent as first event.
+    pub(crate) fn subscribe(&mut self) -> Receiver<ConnectionEvent> {
+        let (tx, rx) = channel();
+        let _ = tx.send(ConnectionEvent::StateChanged(self.state));
+        self.observers.push(tx);
+        rx
+    }
+
+    /// Send the event to all observers. Dropped receivers are removed.
+    fn emit(&mut self, event: ConnectionEvent) {
+        self.observers.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+}
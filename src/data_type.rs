@@ -0,0 +1,864 @@
+use crate::client::ClientResult;
+use crate::error::ClientError;
+use crate::symbol::read_string;
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::HashMap;
+
+/// Data type flag. The entry describes a data type.
+pub const ADSDATATYPEFLAG_DATATYPE: u32 = 0x0001;
+/// Data type flag. The entry describes a data item (struct member).
+pub const ADSDATATYPEFLAG_DATAITEM: u32 = 0x0002;
+/// Data type flag. The data type is a reference.
+pub const ADSDATATYPEFLAG_REFERENCETO: u32 = 0x0004;
+/// Data type flag. A type GUID follows the sub items.
+pub const ADSDATATYPEFLAG_TYPEGUID: u32 = 0x0080;
+/// Data type flag. A copy mask follows the type GUID.
+pub const ADSDATATYPEFLAG_COPYMASK: u32 = 0x0200;
+/// Data type flag. Method infos follow the copy mask.
+pub const ADSDATATYPEFLAG_METHODINFOS: u32 = 0x0800;
+/// Data type flag. Attributes follow the method infos.
+pub const ADSDATATYPEFLAG_ATTRIBUTES: u32 = 0x1000;
+/// Data type flag. Enum infos follow the attributes.
+pub const ADSDATATYPEFLAG_ENUMINFOS: u32 = 0x2000;
+
+/// ADS data type ids (ADST_*) as used in symbol and data type entries
+pub const ADST_INT16: u32 = 2;
+pub const ADST_INT32: u32 = 3;
+pub const ADST_REAL32: u32 = 4;
+pub const ADST_REAL64: u32 = 5;
+pub const ADST_INT8: u32 = 16;
+pub const ADST_UINT8: u32 = 17;
+pub const ADST_UINT16: u32 = 18;
+pub const ADST_UINT32: u32 = 19;
+pub const ADST_INT64: u32 = 20;
+pub const ADST_UINT64: u32 = 21;
+pub const ADST_STRING: u32 = 30;
+pub const ADST_WSTRING: u32 = 31;
+pub const ADST_BIT: u32 = 33;
+
+/// Fixed size part of a data type entry
+const DATA_TYPE_ENTRY_HEADER_SIZE: usize = 42;
+/// Max nesting depth while resolving a type. Protects against alias loops in malformed data.
+const MAX_RESOLVE_DEPTH: usize = 64;
+/// Pointer size if the uploaded dictionary has no pointer entry (64-bit runtime)
+const DEFAULT_POINTER_SIZE: u32 = 8;
+
+/// Array bounds of one dimension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArrayDimension {
+    pub lower_bound: i32,
+    pub elements: u32,
+}
+
+impl ArrayDimension {
+    /// Computed in i64, lower_bound + elements can exceed i32. Empty dimensions return lower_bound - 1.
+    pub fn upper_bound(&self) -> i64 {
+        self.lower_bound as i64 + self.elements as i64 - 1
+    }
+}
+
+/// Name and value of an enum member
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumValue {
+    pub name: String,
+    pub value: i64,
+}
+
+/// A data type entry as uploaded from the data type dictionary (ADSIGRP_SYM_DT_UPLOAD).
+/// Sub items are struct members which are data type entries themselves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataTypeEntry {
+    pub version: u32,
+    pub size: u32,
+    /// Offset of a sub item within its parent struct
+    pub offset: u32,
+    /// ADS data type id (ADST_*)
+    pub data_type: u32,
+    pub flags: u32,
+    pub name: String,
+    pub type_name: String,
+    pub comment: String,
+    pub array_info: Vec<ArrayDimension>,
+    pub sub_items: Vec<DataTypeEntry>,
+    pub enum_infos: Vec<EnumValue>,
+}
+
+impl DataTypeEntry {
+    /// Parse a single data type entry including its sub items. The slice is advanced to the next entry.
+    pub fn parse(data: &mut &[u8]) -> ClientResult<Self> {
+        let entry_length = data.read_u32::<LittleEndian>()? as usize;
+        if entry_length < DATA_TYPE_ENTRY_HEADER_SIZE || entry_length - 4 > data.len() {
+            return Err(ClientError::Decode(format!(
+                "Invalid data type entry length {} ({} bytes available)",
+                entry_length,
+                data.len() + 4
+            )));
+        }
+        let (mut entry, rest) = data.split_at(entry_length - 4);
+        *data = rest;
+
+        let version = entry.read_u32::<LittleEndian>()?;
+        let _hash_value = entry.read_u32::<LittleEndian>()?;
+        let _type_hash_value = entry.read_u32::<LittleEndian>()?;
+        let size = entry.read_u32::<LittleEndian>()?;
+        let offset = entry.read_u32::<LittleEndian>()?;
+        let data_type = entry.read_u32::<LittleEndian>()?;
+        let flags = entry.read_u32::<LittleEndian>()?;
+        let name_length = entry.read_u16::<LittleEndian>()?;
+        let type_length = entry.read_u16::<LittleEndian>()?;
+        let comment_length = entry.read_u16::<LittleEndian>()?;
+        let array_dim = entry.read_u16::<LittleEndian>()?;
+        let sub_item_count = entry.read_u16::<LittleEndian>()?;
+
+        let name = read_string(&mut entry, name_length)?;
+        let type_name = read_string(&mut entry, type_length)?;
+        let comment = read_string(&mut entry, comment_length)?;
+
+        let mut array_info = Vec::with_capacity(array_dim as usize);
+        for _ in 0..array_dim {
+            array_info.push(ArrayDimension {
+                lower_bound: entry.read_i32::<LittleEndian>()?,
+                elements: entry.read_u32::<LittleEndian>()?,
+            });
+        }
+
+        let mut sub_items = Vec::with_capacity(sub_item_count as usize);
+        for _ in 0..sub_item_count {
+            sub_items.push(DataTypeEntry::parse(&mut entry)?);
+        }
+
+        let enum_infos = if flags & ADSDATATYPEFLAG_ENUMINFOS != 0 {
+            read_enum_infos(&mut entry, flags, size)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(DataTypeEntry {
+            version,
+            size,
+            offset,
+            data_type,
+            flags,
+            name,
+            type_name,
+            comment,
+            array_info,
+            sub_items,
+            enum_infos,
+        })
+    }
+}
+
+/// Skip the optional parts in front of the enum infos and read them
+fn read_enum_infos(entry: &mut &[u8], flags: u32, size: u32) -> ClientResult<Vec<EnumValue>> {
+    if flags & ADSDATATYPEFLAG_TYPEGUID != 0 {
+        skip(entry, 16)?;
+    }
+    if flags & ADSDATATYPEFLAG_COPYMASK != 0 {
+        skip(entry, size as usize)?;
+    }
+    if flags & ADSDATATYPEFLAG_METHODINFOS != 0 {
+        let count = entry.read_u16::<LittleEndian>()?;
+        for _ in 0..count {
+            let method_length = entry.read_u32::<LittleEndian>()? as usize;
+            skip(entry, method_length.saturating_sub(4))?;
+        }
+    }
+    if flags & ADSDATATYPEFLAG_ATTRIBUTES != 0 {
+        let count = entry.read_u16::<LittleEndian>()?;
+        for _ in 0..count {
+            let name_length = entry.read_u8()? as usize;
+            let value_length = entry.read_u8()? as usize;
+            skip(entry, name_length + 1 + value_length + 1)?;
+        }
+    }
+
+    let count = entry.read_u16::<LittleEndian>()?;
+    let mut values = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let name_length = entry.read_u8()?;
+        let name = read_string(entry, name_length as u16)?;
+        let value = match size {
+            1 => entry.read_i8()? as i64,
+            2 => entry.read_i16::<LittleEndian>()? as i64,
+            4 => entry.read_i32::<LittleEndian>()? as i64,
+            8 => entry.read_i64::<LittleEndian>()?,
+            _ => {
+                return Err(ClientError::Decode(format!(
+                    "Unsupported enum size {}",
+                    size
+                )))
+            }
+        };
+        values.push(EnumValue { name, value });
+    }
+    Ok(values)
+}
+
+fn skip(data: &mut &[u8], len: usize) -> ClientResult<()> {
+    if data.len() < len {
+        return Err(ClientError::Decode(
+            "Unexpected end of data type entry".to_string(),
+        ));
+    }
+    *data = &data[len..];
+    Ok(())
+}
+
+/// Elementary IEC 61131-3 types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrimitiveType {
+    Bool,
+    Byte,
+    Word,
+    DWord,
+    LWord,
+    SInt,
+    USInt,
+    Int,
+    UInt,
+    DInt,
+    UDInt,
+    LInt,
+    ULInt,
+    Real,
+    LReal,
+    Time,
+    LTime,
+    Date,
+    DateAndTime,
+    TimeOfDay,
+}
+
+impl PrimitiveType {
+    /// Lookup an elementary type by its IEC name (case insensitive)
+    pub fn from_name(name: &str) -> Option<Self> {
+        let primitive = match name.to_uppercase().as_str() {
+            "BOOL" | "BIT" => PrimitiveType::Bool,
+            "BYTE" => PrimitiveType::Byte,
+            "WORD" => PrimitiveType::Word,
+            "DWORD" => PrimitiveType::DWord,
+            "LWORD" => PrimitiveType::LWord,
+            "SINT" => PrimitiveType::SInt,
+            "USINT" => PrimitiveType::USInt,
+            "INT" => PrimitiveType::Int,
+            "UINT" => PrimitiveType::UInt,
+            "DINT" => PrimitiveType::DInt,
+            "UDINT" => PrimitiveType::UDInt,
+            "LINT" => PrimitiveType::LInt,
+            "ULINT" => PrimitiveType::ULInt,
+            "REAL" => PrimitiveType::Real,
+            "LREAL" => PrimitiveType::LReal,
+            "TIME" => PrimitiveType::Time,
+            "LTIME" => PrimitiveType::LTime,
+            "DATE" => PrimitiveType::Date,
+            "DATE_AND_TIME" | "DT" => PrimitiveType::DateAndTime,
+            "TIME_OF_DAY" | "TOD" => PrimitiveType::TimeOfDay,
+            _ => return None,
+        };
+        Some(primitive)
+    }
+
+    /// Lookup an elementary type by its ADS data type id (ADST_*)
+    pub fn from_ads_data_type(data_type: u32) -> Option<Self> {
+        let primitive = match data_type {
+            ADST_BIT => PrimitiveType::Bool,
+            ADST_INT8 => PrimitiveType::SInt,
+            ADST_UINT8 => PrimitiveType::USInt,
+            ADST_INT16 => PrimitiveType::Int,
+            ADST_UINT16 => PrimitiveType::UInt,
+            ADST_INT32 => PrimitiveType::DInt,
+            ADST_UINT32 => PrimitiveType::UDInt,
+            ADST_INT64 => PrimitiveType::LInt,
+            ADST_UINT64 => PrimitiveType::ULInt,
+            ADST_REAL32 => PrimitiveType::Real,
+            ADST_REAL64 => PrimitiveType::LReal,
+            _ => return None,
+        };
+        Some(primitive)
+    }
+
+    /// IEC name of the type
+    pub fn name(&self) -> &'static str {
+        match self {
+            PrimitiveType::Bool => "BOOL",
+            PrimitiveType::Byte => "BYTE",
+            PrimitiveType::Word => "WORD",
+            PrimitiveType::DWord => "DWORD",
+            PrimitiveType::LWord => "LWORD",
+            PrimitiveType::SInt => "SINT",
+            PrimitiveType::USInt => "USINT",
+            PrimitiveType::Int => "INT",
+            PrimitiveType::UInt => "UINT",
+            PrimitiveType::DInt => "DINT",
+            PrimitiveType::UDInt => "UDINT",
+            PrimitiveType::LInt => "LINT",
+            PrimitiveType::ULInt => "ULINT",
+            PrimitiveType::Real => "REAL",
+            PrimitiveType::LReal => "LREAL",
+            PrimitiveType::Time => "TIME",
+            PrimitiveType::LTime => "LTIME",
+            PrimitiveType::Date => "DATE",
+            PrimitiveType::DateAndTime => "DATE_AND_TIME",
+            PrimitiveType::TimeOfDay => "TIME_OF_DAY",
+        }
+    }
+
+    /// Size in bytes
+    pub fn size(&self) -> u32 {
+        match self {
+            PrimitiveType::Bool
+            | PrimitiveType::Byte
+            | PrimitiveType::SInt
+            | PrimitiveType::USInt => 1,
+            PrimitiveType::Word | PrimitiveType::Int | PrimitiveType::UInt => 2,
+            PrimitiveType::DWord
+            | PrimitiveType::DInt
+            | PrimitiveType::UDInt
+            | PrimitiveType::Real
+            | PrimitiveType::Time
+            | PrimitiveType::Date
+            | PrimitiveType::DateAndTime
+            | PrimitiveType::TimeOfDay => 4,
+            PrimitiveType::LWord
+            | PrimitiveType::LInt
+            | PrimitiveType::ULInt
+            | PrimitiveType::LReal
+            | PrimitiveType::LTime => 8,
+        }
+    }
+}
+
+/// A fully resolved data type.
+/// Struct members, array elements, enum base types and alias targets are resolved recursively.
+/// Pointers and references keep the name of their target type to avoid endless recursion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataType {
+    pub name: String,
+    /// Size in bytes
+    pub size: u32,
+    pub comment: String,
+    pub kind: DataTypeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataTypeKind {
+    Primitive(PrimitiveType),
+    /// STRING(n) with n characters (size is n + 1)
+    String(u32),
+    /// WSTRING(n) with n characters (size is 2 * (n + 1))
+    WString(u32),
+    Struct(Vec<StructMember>),
+    Array {
+        dimensions: Vec<ArrayDimension>,
+        element: Box<DataType>,
+    },
+    Enum {
+        base: Box<DataType>,
+        values: Vec<EnumValue>,
+    },
+    Alias(Box<DataType>),
+    Pointer(String),
+    Reference(String),
+}
+
+/// Struct member with its byte offset within the struct
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructMember {
+    pub name: String,
+    pub offset: u32,
+    pub data_type: DataType,
+}
+
+/// The data type dictionary of the target.
+/// Type names are looked up case insensitive like TwinCAT does.
+#[derive(Debug, Clone)]
+pub struct DataTypeTable {
+    entries: Vec<DataTypeEntry>,
+    index: HashMap<String, usize>,
+    /// Size of POINTER TO / REFERENCE TO on the target (4 on 32-bit runtimes)
+    pointer_size: u32,
+}
+
+impl Default for DataTypeTable {
+    fn default() -> Self {
+        DataTypeTable::new(Vec::new())
+    }
+}
+
+impl DataTypeTable {
+    pub fn new(entries: Vec<DataTypeEntry>) -> Self {
+        let index = entries
+            .iter()
+            .enumerate()
+            .map(|(n, e)| (e.name.to_lowercase(), n))
+            .collect();
+        let pointer_size = entries
+            .iter()
+            .find_map(pointer_size)
+            .unwrap_or(DEFAULT_POINTER_SIZE);
+        DataTypeTable {
+            entries,
+            index,
+            pointer_size,
+        }
+    }
+
+    /// Parse the data type dictionary from the data of a ReadResponse (ADSIGRP_SYM_DT_UPLOAD)
+    pub fn parse(mut data: &[u8]) -> ClientResult<Self> {
+        let mut entries = Vec::new();
+        while !data.is_empty() {
+            entries.push(DataTypeEntry::parse(&mut data)?);
+        }
+        Ok(DataTypeTable::new(entries))
+    }
+
+    /// Get the raw data type entry by type name
+    pub fn get(&self, name: &str) -> Option<&DataTypeEntry> {
+        self.index
+            .get(&name.to_lowercase())
+            .map(|n| &self.entries[*n])
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, DataTypeEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Resolve a type by name into a DataType tree.
+    /// Elementary types, STRING(n), WSTRING(n), POINTER TO, REFERENCE TO and ARRAY [a..b] OF
+    /// are resolved even if they are not part of the uploaded dictionary.
+    pub fn resolve(&self, name: &str) -> ClientResult<DataType> {
+        self.resolve_name(name, 0)
+    }
+
+    fn resolve_name(&self, name: &str, depth: usize) -> ClientResult<DataType> {
+        if depth > MAX_RESOLVE_DEPTH {
+            return Err(ClientError::Decode(format!(
+                "Data type {} is nested too deep",
+                name
+            )));
+        }
+        if let Some(entry) = self.get(name) {
+            return self.resolve_entry(entry, depth + 1);
+        }
+        if let Some(t) = builtin(name, self.pointer_size) {
+            return Ok(t);
+        }
+        match parse_array_name(name) {
+            Some((dimensions, element)) => self.resolve_array(name, dimensions, element, depth + 1),
+            None => Err(ClientError::Decode(format!("Data type {} not found", name))),
+        }
+    }
+
+    /// Resolve an array type which has no own dictionary entry (ARRAY [0..1] OF ARRAY [0..2] OF INT)
+    fn resolve_array(
+        &self,
+        name: &str,
+        dimensions: Vec<ArrayDimension>,
+        element: &str,
+        depth: usize,
+    ) -> ClientResult<DataType> {
+        let element = self.resolve_name(element, depth)?;
+        let size = dimensions
+            .iter()
+            .try_fold(element.size, |size, d| size.checked_mul(d.elements))
+            .ok_or_else(|| ClientError::Decode(format!("Array type {} is too large", name)))?;
+        Ok(DataType {
+            name: name.to_string(),
+            size,
+            comment: String::new(),
+            kind: DataTypeKind::Array {
+                dimensions,
+                element: Box::new(element),
+            },
+        })
+    }
+
+    fn resolve_entry(&self, entry: &DataTypeEntry, depth: usize) -> ClientResult<DataType> {
+        let kind = if !entry.enum_infos.is_empty() {
+            let base = match PrimitiveType::from_ads_data_type(entry.data_type) {
+                Some(p) if entry.type_name.is_empty() => primitive(p),
+                _ => self.resolve_name(&entry.type_name, depth)?,
+            };
+            DataTypeKind::Enum {
+                base: Box::new(base),
+                values: entry.enum_infos.clone(),
+            }
+        } else if !entry.array_info.is_empty() {
+            DataTypeKind::Array {
+                dimensions: entry.array_info.clone(),
+                element: Box::new(self.resolve_name(array_element_name(&entry.type_name), depth)?),
+            }
+        } else if !entry.sub_items.is_empty() {
+            let mut members = Vec::with_capacity(entry.sub_items.len());
+            for item in &entry.sub_items {
+                members.push(StructMember {
+                    name: item.name.clone(),
+                    offset: item.offset,
+                    data_type: self.resolve_item(item, depth)?,
+                });
+            }
+            DataTypeKind::Struct(members)
+        } else if let Some(t) = builtin(&entry.name, entry.size) {
+            t.kind
+        } else if entry.flags & ADSDATATYPEFLAG_REFERENCETO != 0 {
+            DataTypeKind::Reference(entry.type_name.clone())
+        } else if !entry.type_name.is_empty() && !entry.type_name.eq_ignore_ascii_case(&entry.name)
+        {
+            DataTypeKind::Alias(Box::new(self.resolve_name(&entry.type_name, depth)?))
+        } else if let Some(p) = PrimitiveType::from_ads_data_type(entry.data_type) {
+            DataTypeKind::Primitive(p)
+        } else {
+            return Err(ClientError::Decode(format!(
+                "Unable to resolve data type {}",
+                entry.name
+            )));
+        };
+
+        Ok(DataType {
+            name: entry.name.clone(),
+            size: entry.size,
+            comment: entry.comment.clone(),
+            kind,
+        })
+    }
+
+    /// Resolve the type of a struct member.
+    /// Inline arrays (a : ARRAY [0..9] OF INT) may not have an own dictionary entry.
+    fn resolve_item(&self, item: &DataTypeEntry, depth: usize) -> ClientResult<DataType> {
+        match self.resolve_name(&item.type_name, depth) {
+            Ok(mut t) => {
+                if let DataTypeKind::Pointer(_) | DataTypeKind::Reference(_) = t.kind {
+                    t.size = item.size;
+                }
+                Ok(t)
+            }
+            Err(_) if !item.array_info.is_empty() => Ok(DataType {
+                name: item.type_name.clone(),
+                size: item.size,
+                comment: item.comment.clone(),
+                kind: DataTypeKind::Array {
+                    dimensions: item.array_info.clone(),
+                    element: Box::new(
+                        self.resolve_name(array_element_name(&item.type_name), depth)?,
+                    ),
+                },
+            }),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn primitive(p: PrimitiveType) -> DataType {
+    DataType {
+        name: p.name().to_string(),
+        size: p.size(),
+        comment: String::new(),
+        kind: DataTypeKind::Primitive(p),
+    }
+}
+
+/// Types which can be resolved by their name only
+fn builtin(name: &str, pointer_size: u32) -> Option<DataType> {
+    let upper = name.trim().to_ascii_uppercase();
+    let (size, kind) = if let Some(p) = PrimitiveType::from_name(&upper) {
+        (p.size(), DataTypeKind::Primitive(p))
+    } else if let Some(len) = string_length(&upper, "WSTRING") {
+        (2 * (len + 1), DataTypeKind::WString(len))
+    } else if let Some(len) = string_length(&upper, "STRING") {
+        (len + 1, DataTypeKind::String(len))
+    } else if upper.starts_with("POINTER TO ") {
+        (
+            pointer_size,
+            DataTypeKind::Pointer(name.trim()[11..].to_string()),
+        )
+    } else if upper.starts_with("REFERENCE TO ") {
+        (
+            pointer_size,
+            DataTypeKind::Reference(name.trim()[13..].to_string()),
+        )
+    } else {
+        return None;
+    };
+    Some(DataType {
+        name: name.to_string(),
+        size,
+        comment: String::new(),
+        kind,
+    })
+}
+
+/// Length of STRING(n) / WSTRING(n). Plain STRING / WSTRING default to 80 characters.
+fn string_length(name: &str, prefix: &str) -> Option<u32> {
+    let rest = name.strip_prefix(prefix)?.trim();
+    if rest.is_empty() {
+        return Some(80);
+    }
+    rest.strip_prefix('(')?
+        .strip_suffix(')')?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// "ARRAY [0..9] OF INT" --> "INT"
+fn array_element_name(type_name: &str) -> &str {
+    let upper = type_name.to_ascii_uppercase();
+    match upper.find(" OF ") {
+        Some(n) if upper.starts_with("ARRAY") => type_name[n + 4..].trim(),
+        _ => type_name,
+    }
+}
+
+/// "ARRAY [0..1, -2..2] OF INT" --> dimensions and "INT".
+/// None if the bounds are not numeric (constants are resolved by the PLC only).
+fn parse_array_name(type_name: &str) -> Option<(Vec<ArrayDimension>, &str)> {
+    let upper = type_name.to_ascii_uppercase();
+    if !upper.trim_start().starts_with("ARRAY") {
+        return None;
+    }
+    let open = type_name.find('[')?;
+    let close = open + type_name[open..].find(']')?;
+    let of = close + upper[close..].find(" OF ")?;
+    let mut dimensions = Vec::new();
+    for range in type_name[open + 1..close].split(',') {
+        let (lower, upper) = range.split_once("..")?;
+        let lower: i32 = lower.trim().parse().ok()?;
+        let upper: i32 = upper.trim().parse().ok()?;
+        let elements = u32::try_from(upper as i64 - lower as i64 + 1).ok()?;
+        dimensions.push(ArrayDimension {
+            lower_bound: lower,
+            elements,
+        });
+    }
+    Some((dimensions, type_name[of + 4..].trim()))
+}
+
+/// Size of the first pointer or reference in the dictionary
+fn pointer_size(entry: &DataTypeEntry) -> Option<u32> {
+    let is_pointer = |name: &str| {
+        let upper = name.trim().to_ascii_uppercase();
+        upper.starts_with("POINTER TO ") || upper.starts_with("REFERENCE TO ")
+    };
+    if is_pointer(&entry.name) || (entry.array_info.is_empty() && is_pointer(&entry.type_name)) {
+        return Some(entry.size);
+    }
+    entry.sub_items.iter().find_map(pointer_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Data type upload of ST_Test and E_Mode from a 32-bit runtime
+    fn fixture() -> Vec<u8> {
+        let chunks: &[&[u8]] = &[
+            //ST_Test: length 271, version 1, hashes, size 16, offset 0, data type 65, flags 0x1
+            &[
+                0x0F, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x41, 0x00, 0x00, 0x00,
+                0x01, 0x00, 0x00, 0x00,
+                //name, type, comment length, array dimensions, sub items
+                0x07, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x03, 0x00,
+            ],
+            b"ST_Test\0",
+            b"\0",
+            b"test\0",
+            //nValue : INT: length 54, version 1, hashes, size 2, offset 0, data type 2, flags 0x2
+            &[
+                0x36, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+                0x02, 0x00, 0x00, 0x00,
+                //name, type, comment length, array dimensions, sub items
+                0x06, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+            b"nValue\0",
+            b"INT\0",
+            b"\0",
+            //pValue : POINTER TO INT (32-bit runtime): length 65, version 1, hashes, size 4, offset 4, data type 19, flags 0x2
+            &[
+                0x41, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x13, 0x00, 0x00, 0x00,
+                0x02, 0x00, 0x00, 0x00,
+                //name, type, comment length, array dimensions, sub items
+                0x06, 0x00, 0x0E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+            b"pValue\0",
+            b"POINTER TO INT\0",
+            b"\0",
+            //aMatrix : ARRAY [0..1] OF ARRAY [1..3] OF BYTE: length 96, version 1, hashes, size 6, offset 8, data type 65, flags 0x2
+            &[
+                0x60, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x41, 0x00, 0x00, 0x00,
+                0x02, 0x00, 0x00, 0x00,
+                //name, type, comment length, array dimensions, sub items
+                0x07, 0x00, 0x24, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+            ],
+            b"aMatrix\0",
+            b"ARRAY [0..1] OF ARRAY [1..3] OF BYTE\0",
+            b"\0",
+            //array dimension [0..1]
+            &[0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00],
+            //E_Mode: length 69, version 1, hashes, size 2, offset 0, data type 2, flags 0x2001
+            &[
+                0x45, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+                0x01, 0x20, 0x00, 0x00,
+                //name, type, comment length, array dimensions, sub items
+                0x06, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+            b"E_Mode\0",
+            b"INT\0",
+            b"\0",
+            //enum infos: count, (name length, name, value)...
+            &[0x02, 0x00, 0x03],
+            b"Off\0",
+            &[0x00, 0x00, 0x02],
+            b"On\0",
+            &[0x01, 0x00],
+        ];
+        chunks.concat()
+    }
+
+    #[test]
+    fn parse_entries() {
+        let table = DataTypeTable::parse(&fixture()).unwrap();
+        assert_eq!(table.len(), 2);
+
+        let st = table.get("st_test").unwrap();
+        assert_eq!(st.size, 16);
+        assert_eq!(st.comment, "test");
+        assert_eq!(st.sub_items.len(), 3);
+        let matrix = &st.sub_items[2];
+        assert_eq!(matrix.name, "aMatrix");
+        assert_eq!(matrix.offset, 8);
+        assert_eq!(
+            matrix.array_info,
+            vec![ArrayDimension {
+                lower_bound: 0,
+                elements: 2
+            }]
+        );
+
+        let mode = table.get("E_Mode").unwrap();
+        assert_eq!(
+            mode.enum_infos,
+            vec![
+                EnumValue {
+                    name: "Off".to_string(),
+                    value: 0
+                },
+                EnumValue {
+                    name: "On".to_string(),
+                    value: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_struct() {
+        let table = DataTypeTable::parse(&fixture()).unwrap();
+        let st = table.resolve("ST_Test").unwrap();
+        let members = match st.kind {
+            DataTypeKind::Struct(members) => members,
+            kind => panic!("Not a struct: {:?}", kind),
+        };
+        assert_eq!(members[0].data_type, primitive(PrimitiveType::Int));
+
+        //Pointer size of the 32-bit runtime
+        assert_eq!(members[1].offset, 4);
+        assert_eq!(members[1].data_type.size, 4);
+        assert_eq!(
+            members[1].data_type.kind,
+            DataTypeKind::Pointer("INT".to_string())
+        );
+        assert_eq!(table.resolve("REFERENCE TO LREAL").unwrap().size, 4);
+
+        //ARRAY [0..1] OF ARRAY [1..3] OF BYTE
+        let matrix = &members[2].data_type;
+        assert_eq!(matrix.size, 6);
+        let inner = match &matrix.kind {
+            DataTypeKind::Array {
+                dimensions,
+                element,
+            } => {
+                assert_eq!(dimensions[0].upper_bound(), 1);
+                element
+            }
+            kind => panic!("Not an array: {:?}", kind),
+        };
+        assert_eq!(inner.size, 3);
+        match &inner.kind {
+            DataTypeKind::Array {
+                dimensions,
+                element,
+            } => {
+                assert_eq!(dimensions[0].lower_bound, 1);
+                assert_eq!(dimensions[0].upper_bound(), 3);
+                assert_eq!(**element, primitive(PrimitiveType::Byte));
+            }
+            kind => panic!("Not an array: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn resolve_enum() {
+        let table = DataTypeTable::parse(&fixture()).unwrap();
+        match table.resolve("E_Mode").unwrap().kind {
+            DataTypeKind::Enum { base, values } => {
+                assert_eq!(*base, primitive(PrimitiveType::Int));
+                assert_eq!(values.len(), 2);
+            }
+            kind => panic!("Not an enum: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn resolve_without_dictionary() {
+        let table = DataTypeTable::default();
+        assert_eq!(table.resolve("POINTER TO INT").unwrap().size, 8);
+        assert_eq!(table.resolve("STRING(10)").unwrap().size, 11);
+        assert_eq!(
+            table
+                .resolve("ARRAY [-1..1, 0..1] OF WSTRING")
+                .unwrap()
+                .size,
+            6 * 162
+        );
+        assert!(table.resolve("ARRAY [0..MAX] OF INT").is_err());
+        assert!(table.resolve("ST_Unknown").is_err());
+    }
+
+    #[test]
+    fn truncated_entry() {
+        let data = fixture();
+        assert!(DataTypeTable::parse(&data[..data.len() - 1]).is_err());
+        assert!(DataTypeTable::parse(&data[..20]).is_err());
+        //Entry length shorter than the strings it announces
+        let mut data = fixture();
+        data[0] = 42;
+        assert!(DataTypeTable::parse(&data).is_err());
+    }
+
+    #[test]
+    fn upper_bound_does_not_overflow() {
+        let dimension = |lower_bound, elements| ArrayDimension {
+            lower_bound,
+            elements,
+        };
+        assert_eq!(dimension(-2, 5).upper_bound(), 2);
+        assert_eq!(dimension(0, 0).upper_bound(), -1);
+        assert_eq!(dimension(i32::MAX, 2).upper_bound(), i32::MAX as i64 + 1);
+        assert_eq!(dimension(i32::MIN, 1).upper_bound(), i32::MIN as i64);
+        assert_eq!(dimension(1, u32::MAX).upper_bound(), u32::MAX as i64);
+    }
+}
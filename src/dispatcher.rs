@@ -0,0 +1,102 @@
+use crate::client::{ClientResult, TimeStamp};
+use ads_proto::proto::response::AdsNotificationSample;
+use std::collections::HashMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+
+type Sample = ClientResult<(AdsNotificationSample, TimeStamp)>;
+/// Callback of a device notification. See Client::add_device_notification_with_callback
+pub(crate) type NotificationCallback = Box<dyn FnMut(Sample) + Send>;
+
+pub(crate) enum DispatchMessage {
+    Add(u64, NotificationCallback),
+    Sample(u64, Sample),
+    Remove(u64),
+}
+
+/// Destination of the samples of a device notification forwarded by the reader thread
+#[derive(Debug, Clone)]
+pub(crate) enum NotificationSink {
+    /// Receiver returned by add_device_notification
+    Channel(Sender<Sample>),
+    /// Callback with id on the dispatcher thread
+    Callback(u64, Sender<DispatchMessage>),
+}
+
+impl NotificationSink {
+    /// Forward a sample. Returns false if the receiver or the dispatcher thread hung up.
+    pub(crate) fn send(&self, sample: Sample) -> bool {
+        match self {
+            NotificationSink::Channel(tx) => tx.send(sample).is_ok(),
+            NotificationSink::Callback(id, tx) => {
+                tx.send(DispatchMessage::Sample(*id, sample)).is_ok()
+            }
+        }
+    }
+
+    /// Drop the callback. Later samples are ignored by the dispatcher thread.
+    pub(crate) fn close(&self) {
+        if let NotificationSink::Callback(id, tx) = self {
+            let _ = tx.send(DispatchMessage::Remove(*id));
+        }
+    }
+}
+
+/// Sender to the dispatcher thread which runs the notification callbacks of a client.
+/// The thread is started with the first callback and ends with the client.
+#[derive(Debug, Default)]
+pub(crate) struct Dispatcher {
+    tx: Option<Sender<DispatchMessage>>,
+    next_id: u64,
+}
+
+impl Dispatcher {
+    /// Pass a callback to the dispatcher thread. Returns the sink for the reader thread.
+    pub(crate) fn add(&mut self, callback: NotificationCallback) -> ClientResult<NotificationSink> {
+        let tx = match &self.tx {
+            Some(tx) => tx.clone(),
+            None => self.tx.insert(run_dispatcher_thread()?).clone(),
+        };
+        self.next_id += 1;
+        let id = self.next_id;
+        //The thread runs as long as tx is kept, panics of callbacks are caught
+        let _ = tx.send(DispatchMessage::Add(id, callback));
+        Ok(NotificationSink::Callback(id, tx))
+    }
+
+    /// True if no callback has been added yet
+    #[cfg(test)]
+    pub(crate) fn is_idle(&self) -> bool {
+        self.tx.is_none()
+    }
+}
+
+/// Run the callbacks one after another so slow callbacks never stall the reader thread.
+/// A callback which panics is dropped, the other callbacks keep running.
+fn run_dispatcher_thread() -> ClientResult<Sender<DispatchMessage>> {
+    let (tx, rx) = channel::<DispatchMessage>();
+    thread::Builder::new()
+        .name("ads-dispatcher".to_string())
+        .spawn(move || {
+            let mut callbacks: HashMap<u64, NotificationCallback> = HashMap::new();
+            for message in rx {
+                match message {
+                    DispatchMessage::Add(id, callback) => {
+                        callbacks.insert(id, callback);
+                    }
+                    DispatchMessage::Sample(id, sample) => {
+                        if let Some(callback) = callbacks.get_mut(&id) {
+                            if catch_unwind(AssertUnwindSafe(|| callback(sample))).is_err() {
+                                callbacks.remove(&id);
+                            }
+                        }
+                    }
+                    DispatchMessage::Remove(id) => {
+                        callbacks.remove(&id);
+                    }
+                }
+            }
+        })?;
+    Ok(tx)
+}
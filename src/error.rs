@@ -0,0 +1,111 @@
+use ads_proto::error::AdsError;
+use std::fmt;
+use std::time::Duration;
+
+/// Error returned by all client methods
+#[derive(Debug)]
+pub enum ClientError {
+    /// I/O error on the TCP connection or unexpected end of received data
+    Io(std::io::Error),
+    /// Error code returned by the target in the result of a response
+    Ads(AdsError),
+    /// Error code in the AMS header of the response to the request with invoke_id (e.g. target port not found)
+    Ams { invoke_id: u32, error: AdsError },
+    /// No response has been received within the request timeout
+    Timeout { invoke_id: u32, timeout: Duration },
+    /// The client is not connected or the connection has been lost
+    NotConnected,
+    /// The symbol is not known by the target
+    SymbolNotFound { name: String },
+    /// The client holds no handle for this name (var handle or notification handle)
+    HandleNotFound { name: String },
+    /// Data could not be decoded or encoded (too short, does not fit the PLC type...)
+    Decode(String),
+    /// The target answered in an unexpected way (wrong response type or length, malformed packet)
+    ProtocolViolation(String),
+    /// Some sub requests of a sumup request failed. Contains the name and the error code of every failed sub request.
+    SumupPartial { failed: Vec<(String, AdsError)> },
+}
+
+impl ClientError {
+    /// The ADS error code if the error has been reported by the target (Ads and Ams)
+    pub fn ads_error(&self) -> Option<&AdsError> {
+        match self {
+            ClientError::Ads(e) => Some(e),
+            ClientError::Ams { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "I/O error: {}", e),
+            ClientError::Ads(e) => write!(f, "ADS error: {:?}", e),
+            ClientError::Ams { invoke_id, error } => write!(
+                f,
+                "AMS error {:?} in response to request {}",
+                error, invoke_id
+            ),
+            ClientError::Timeout { invoke_id, timeout } => write!(
+                f,
+                "No response for request {} within {:?}",
+                invoke_id, timeout
+            ),
+            ClientError::NotConnected => write!(f, "Not connected"),
+            ClientError::SymbolNotFound { name } => write!(f, "Symbol {} not found", name),
+            ClientError::HandleNotFound { name } => write!(f, "No handle available for {}", name),
+            ClientError::Decode(msg) => write!(f, "Decode error: {}", msg),
+            ClientError::ProtocolViolation(msg) => write!(f, "Protocol violation: {}", msg),
+            ClientError::SumupPartial { failed } => {
+                write!(
+                    f,
+                    "{} sub requests of the sumup request failed",
+                    failed.len()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(e: std::io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+impl From<AdsError> for ClientError {
+    fn from(e: AdsError) -> Self {
+        ClientError::Ads(e)
+    }
+}
+
+/// Value does not fit the PLC type
+impl From<std::num::TryFromIntError> for ClientError {
+    fn from(e: std::num::TryFromIntError) -> Self {
+        ClientError::Decode(e.to_string())
+    }
+}
+
+/// Errors of ads-proto (parsing, response conversion)
+impl From<anyhow::Error> for ClientError {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<AdsError>() {
+            Ok(e) => ClientError::Ads(e),
+            Err(e) => match e.downcast::<std::io::Error>() {
+                Ok(e) => ClientError::Io(e),
+                Err(e) => ClientError::ProtocolViolation(e.to_string()),
+            },
+        }
+    }
+}
@@ -1,3 +1,16 @@
+pub mod ads_type;
+#[cfg(feature = "tokio")]
+pub mod async_client;
 pub mod client;
+pub mod connection_state;
+pub mod data_type;
+mod dispatcher;
+pub mod error;
+pub mod notification;
+pub mod plc_value;
+pub mod read_group;
 mod reader;
+pub mod reconnect;
 pub mod request_factory;
+mod sumup;
+pub mod symbol;
@@ -0,0 +1,381 @@
+use crate::ads_type::AdsType;
+use crate::client::{ClientResult, NotificationReceiver, TimeStamp, WeakClient};
+use crate::error::ClientError;
+use ads_proto::error::AdsError;
+use ads_proto::proto::ads_transition_mode::AdsTransMode;
+use ads_proto::proto::response::AdsNotificationSample;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::mpsc::{Iter, RecvError, RecvTimeoutError, TryRecvError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 100ns intervals between 1601-01-01 (Windows FILETIME epoch) and 1970-01-01
+const FILETIME_UNIX_EPOCH: u64 = 116_444_736_000_000_000;
+
+/// Parameters of a device notification. See Client::subscribe and Client::add_device_notification_with_settings
+///
+/// Build it with the transmission mode and chain the other parameters:
+/// `NotificationSettings::new(AdsTransMode::Cyclic).cycle_time(Duration::from_millis(100))`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NotificationSettings {
+    /// Cyclic, OnChange, CyclicInContext, OnChangeInContext, ClientCycle or ClientOnChange
+    pub transmission_mode: AdsTransMode,
+    /// The host sends a changed value at the latest after max_delay
+    pub max_delay: Duration,
+    /// The host checks the value every cycle_time (zero = every PLC cycle)
+    pub cycle_time: Duration,
+    /// Unit of max_delay and cycle_time expected by the target
+    pub time_unit: NotificationTimeUnit,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings::new(AdsTransMode::OnChange)
+    }
+}
+
+impl NotificationSettings {
+    pub fn new(transmission_mode: AdsTransMode) -> Self {
+        NotificationSettings {
+            transmission_mode,
+            max_delay: Duration::ZERO,
+            cycle_time: Duration::ZERO,
+            time_unit: NotificationTimeUnit::default(),
+        }
+    }
+
+    /// Value is sent when it changed, checked every cycle_time
+    pub fn on_change() -> Self {
+        NotificationSettings::new(AdsTransMode::OnChange)
+    }
+
+    /// Value is sent every cycle_time
+    pub fn cyclic(cycle_time: Duration) -> Self {
+        NotificationSettings::new(AdsTransMode::Cyclic).cycle_time(cycle_time)
+    }
+
+    pub fn transmission_mode(mut self, transmission_mode: AdsTransMode) -> Self {
+        self.transmission_mode = transmission_mode;
+        self
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn cycle_time(mut self, cycle_time: Duration) -> Self {
+        self.cycle_time = cycle_time;
+        self
+    }
+
+    pub fn time_unit(mut self, time_unit: NotificationTimeUnit) -> Self {
+        self.time_unit = time_unit;
+        self
+    }
+
+    /// max_delay in the unit of the target as sent in AddDeviceNotification
+    pub fn max_delay_ticks(&self) -> ClientResult<u32> {
+        self.time_unit.ticks(self.max_delay)
+    }
+
+    /// cycle_time in the unit of the target as sent in AddDeviceNotification
+    pub fn cycle_time_ticks(&self) -> ClientResult<u32> {
+        self.time_unit.ticks(self.cycle_time)
+    }
+}
+
+/// Unit of the times of a device notification on the wire
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NotificationTimeUnit {
+    /// 100ns ticks (TwinCAT 2 and 3 router)
+    #[default]
+    Ticks100ns,
+    /// Milliseconds (some older or third party ADS servers)
+    Milliseconds,
+}
+
+impl NotificationTimeUnit {
+    /// Duration in this unit. Fails if the duration does not fit into u32
+    /// (about 7 minutes in 100ns ticks, about 49 days in milliseconds).
+    pub fn ticks(self, duration: Duration) -> ClientResult<u32> {
+        let ticks = match self {
+            NotificationTimeUnit::Ticks100ns => duration.as_nanos() / 100,
+            NotificationTimeUnit::Milliseconds => duration.as_millis(),
+        };
+        u32::try_from(ticks).map_err(|_| {
+            ClientError::Decode(format!(
+                "Notification time {:?} exceeds the range of {:?}",
+                duration, self
+            ))
+        })
+    }
+}
+
+/// Source of the values of a device notification
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NotificationTarget {
+    /// Var name. The notification is added with the var handle, which is requested again after a reconnect.
+    Symbol(String),
+    /// Index group and index offset (e.g. process image, device data)
+    Address { index_group: u32, index_offset: u32 },
+}
+
+impl fmt::Display for NotificationTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotificationTarget::Symbol(name) => write!(f, "{}", name),
+            NotificationTarget::Address {
+                index_group,
+                index_offset,
+            } => write!(f, "0x{:X}:0x{:X}", index_group, index_offset),
+        }
+    }
+}
+
+/// Decoded sample of a device notification
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification<T> {
+    pub value: T,
+    /// Time stamp of the sample set by the host
+    pub timestamp: SystemTime,
+    /// Notification handle
+    pub handle: u32,
+}
+
+impl<T: AdsType> Notification<T> {
+    /// Decode a sample received by add_device_notification
+    pub fn decode(sample: &AdsNotificationSample, timestamp: TimeStamp) -> ClientResult<Self> {
+        Ok(Notification {
+            value: T::decode(&sample.data)?,
+            timestamp: timestamp_to_system_time(timestamp),
+            handle: sample.notification_handle,
+        })
+    }
+}
+
+/// Token of a device notification. The notification is deleted on the host when the token is dropped.
+/// See Client::add_device_notification_with_callback and Subscription
+#[derive(Debug)]
+pub struct NotificationToken {
+    id: Option<u64>,
+    client: WeakClient,
+}
+
+impl NotificationToken {
+    pub(crate) fn new(id: u64, client: WeakClient) -> Self {
+        NotificationToken {
+            id: Some(id),
+            client,
+        }
+    }
+
+    /// Delete the notification on the host and return the error instead of ignoring it like drop
+    pub fn unsubscribe(mut self) -> ClientResult<()> {
+        self.delete()
+    }
+
+    /// Id of the notification in the client. None after unsubscribe.
+    pub(crate) fn id(&self) -> Option<u64> {
+        self.id
+    }
+
+    fn delete(&mut self) -> ClientResult<()> {
+        if let (Some(id), Some(client)) = (self.id.take(), self.client.upgrade()) {
+            if let Some(response) = client.delete_notification(id)? {
+                if response.result != AdsError::ErrNoError {
+                    return Err(ClientError::Ads(response.result));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for NotificationToken {
+    fn drop(&mut self) {
+        let _ = self.delete();
+    }
+}
+
+type Sample = ClientResult<(AdsNotificationSample, TimeStamp)>;
+
+/// Device notification returned by Client::add_device_notification. Receives the samples like a Receiver.
+/// The notification is deleted on the host when the subscription is dropped, so no notification slot of the host leaks.
+#[derive(Debug)]
+pub struct Subscription {
+    rx: NotificationReceiver,
+    token: NotificationToken,
+}
+
+impl Subscription {
+    pub(crate) fn new(rx: NotificationReceiver, token: NotificationToken) -> Self {
+        Subscription { rx, token }
+    }
+
+    /// Wait for the next sample. Fails after the notification has been deleted.
+    pub fn recv(&self) -> Result<Sample, RecvError> {
+        self.rx.recv()
+    }
+
+    pub fn try_recv(&self) -> Result<Sample, TryRecvError> {
+        self.rx.try_recv()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Sample, RecvTimeoutError> {
+        self.rx.recv_timeout(timeout)
+    }
+
+    /// Iterate over the samples. Blocks until the next sample is received.
+    pub fn iter(&self) -> Iter<'_, Sample> {
+        self.rx.iter()
+    }
+
+    pub fn receiver(&self) -> &NotificationReceiver {
+        &self.rx
+    }
+
+    /// Delete the notification on the host and return the error instead of ignoring it like drop
+    pub fn unsubscribe(self) -> ClientResult<()> {
+        self.token.unsubscribe()
+    }
+}
+
+impl<'a> IntoIterator for &'a Subscription {
+    type Item = Sample;
+    type IntoIter = Iter<'a, Sample>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rx.iter()
+    }
+}
+
+/// Subscription returned by Client::subscribe. The samples are decoded to T when they are received.
+/// The notification is deleted on the host when the subscription is dropped.
+#[derive(Debug)]
+pub struct TypedSubscription<T> {
+    subscription: Subscription,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T: AdsType> TypedSubscription<T> {
+    pub(crate) fn new(subscription: Subscription) -> Self {
+        TypedSubscription {
+            subscription,
+            _value: PhantomData,
+        }
+    }
+
+    /// Wait for the next sample. Fails after the notification has been deleted.
+    pub fn recv(&self) -> Result<ClientResult<Notification<T>>, RecvError> {
+        self.subscription.recv().map(decode_sample)
+    }
+
+    pub fn try_recv(&self) -> Result<ClientResult<Notification<T>>, TryRecvError> {
+        self.subscription.try_recv().map(decode_sample)
+    }
+
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<ClientResult<Notification<T>>, RecvTimeoutError> {
+        self.subscription.recv_timeout(timeout).map(decode_sample)
+    }
+
+    /// Iterate over the decoded samples. Blocks until the next sample is received.
+    pub fn iter(&self) -> impl Iterator<Item = ClientResult<Notification<T>>> + '_ {
+        self.subscription.iter().map(decode_sample)
+    }
+
+    /// Delete the notification on the host and return the error instead of ignoring it like drop
+    pub fn unsubscribe(self) -> ClientResult<()> {
+        self.subscription.unsubscribe()
+    }
+}
+
+fn decode_sample<T: AdsType>(sample: Sample) -> ClientResult<Notification<T>> {
+    sample.and_then(|(sample, timestamp)| Notification::decode(&sample, timestamp))
+}
+
+/// Convert the time stamp of a notification (Windows FILETIME, 100ns intervals since 1601-01-01) into SystemTime
+pub fn timestamp_to_system_time(timestamp: TimeStamp) -> SystemTime {
+    if timestamp >= FILETIME_UNIX_EPOCH {
+        UNIX_EPOCH + filetime_duration(timestamp - FILETIME_UNIX_EPOCH)
+    } else {
+        UNIX_EPOCH - filetime_duration(FILETIME_UNIX_EPOCH - timestamp)
+    }
+}
+
+/// Duration of a number of 100ns intervals
+fn filetime_duration(intervals: u64) -> Duration {
+    Duration::from_secs(intervals / 10_000_000) + Duration::from_nanos(intervals % 10_000_000 * 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_in_100ns() {
+        let unit = NotificationTimeUnit::Ticks100ns;
+        assert_eq!(unit.ticks(Duration::ZERO).unwrap(), 0);
+        assert_eq!(unit.ticks(Duration::from_millis(10)).unwrap(), 100_000);
+        assert_eq!(unit.ticks(Duration::from_nanos(150)).unwrap(), 1);
+        assert_eq!(
+            unit.ticks(Duration::from_nanos(u32::MAX as u64 * 100))
+                .unwrap(),
+            u32::MAX
+        );
+        assert!(unit.ticks(Duration::from_secs(430)).is_err());
+    }
+
+    #[test]
+    fn ticks_in_milliseconds() {
+        let unit = NotificationTimeUnit::Milliseconds;
+        assert_eq!(unit.ticks(Duration::from_secs(2)).unwrap(), 2000);
+        assert_eq!(unit.ticks(Duration::from_secs(430)).unwrap(), 430_000);
+        assert!(unit
+            .ticks(Duration::from_millis(u32::MAX as u64 + 1))
+            .is_err());
+    }
+
+    #[test]
+    fn timestamp_after_1970() {
+        //2021-01-01 00:00:00.5 UTC
+        let timestamp = FILETIME_UNIX_EPOCH + 1_609_459_200 * 10_000_000 + 5_000_000;
+        assert_eq!(
+            timestamp_to_system_time(timestamp),
+            UNIX_EPOCH + Duration::from_millis(1_609_459_200_500)
+        );
+        assert_eq!(timestamp_to_system_time(FILETIME_UNIX_EPOCH), UNIX_EPOCH);
+    }
+
+    #[test]
+    fn timestamp_before_1970() {
+        //1969-12-31 23:59:58.9 UTC
+        let timestamp = FILETIME_UNIX_EPOCH - 11_000_000;
+        assert_eq!(
+            timestamp_to_system_time(timestamp),
+            UNIX_EPOCH - Duration::from_millis(1100)
+        );
+    }
+
+    #[test]
+    fn settings_builder() {
+        let settings = NotificationSettings::cyclic(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(500))
+            .time_unit(NotificationTimeUnit::Milliseconds);
+        assert_eq!(settings.transmission_mode, AdsTransMode::Cyclic);
+        assert_eq!(settings.cycle_time_ticks().unwrap(), 100);
+        assert_eq!(settings.max_delay_ticks().unwrap(), 500);
+
+        let settings = NotificationSettings::on_change();
+        assert_eq!(settings, NotificationSettings::default());
+        assert_eq!(settings.transmission_mode, AdsTransMode::OnChange);
+        assert_eq!(settings.cycle_time, Duration::ZERO);
+        assert_eq!(settings.time_unit, NotificationTimeUnit::Ticks100ns);
+
+        let settings = settings.cycle_time(Duration::from_secs(3600));
+        assert!(settings.cycle_time_ticks().is_err());
+    }
+}
@@ -0,0 +1,516 @@
+use crate::ads_type::{check_len, AdsType, DateAndTime};
+use crate::client::ClientResult;
+use crate::data_type::{ArrayDimension, DataType, DataTypeKind, PrimitiveType};
+use crate::error::ClientError;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A PLC value decoded at runtime with the help of the uploaded data type information.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlcValue {
+    Bool(bool),
+    /// SINT, INT, DINT, LINT
+    Int(i64),
+    /// BYTE, WORD, DWORD, LWORD, USINT, UINT, UDINT, ULINT
+    UInt(u64),
+    /// REAL, LREAL
+    Real(f64),
+    /// TIME, LTIME, TIME_OF_DAY
+    Time(Duration),
+    /// DATE, DATE_AND_TIME
+    DateTime(SystemTime),
+    /// STRING(n), WSTRING(n)
+    String(String),
+    /// Struct members in declaration order
+    Struct(Vec<(String, PlcValue)>),
+    /// Multi dimensional arrays are nested arrays
+    Array(Vec<PlcValue>),
+    /// Name is empty if the value is not part of the enum declaration
+    Enum {
+        name: String,
+        value: i64,
+    },
+    /// POINTER TO, REFERENCE TO (raw address)
+    Pointer(u64),
+}
+
+impl PlcValue {
+    /// Decode the PLC representation of data_type
+    pub fn decode(data_type: &DataType, data: &[u8]) -> ClientResult<Self> {
+        check_len(data, data_type.size as usize)?;
+        let data = &data[..data_type.size as usize];
+        let value = match &data_type.kind {
+            DataTypeKind::Primitive(p) => decode_primitive(*p, data)?,
+            DataTypeKind::String(_) => {
+                let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+                PlcValue::String(String::from_utf8_lossy(&data[..end]).to_string())
+            }
+            DataTypeKind::WString(_) => {
+                let units: Vec<u16> = data
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .take_while(|u| *u != 0)
+                    .collect();
+                PlcValue::String(String::from_utf16_lossy(&units))
+            }
+            DataTypeKind::Struct(members) => {
+                let mut values = Vec::with_capacity(members.len());
+                for member in members {
+                    let offset = member.offset as usize;
+                    let value =
+                        PlcValue::decode(&member.data_type, data.get(offset..).unwrap_or(&[]))?;
+                    values.push((member.name.clone(), value));
+                }
+                PlcValue::Struct(values)
+            }
+            DataTypeKind::Array {
+                dimensions,
+                element,
+            } => decode_array(dimensions, element, data)?,
+            DataTypeKind::Enum { base, values } => {
+                let value = match PlcValue::decode(base, data)? {
+                    PlcValue::Int(v) => v,
+                    PlcValue::UInt(v) => v as i64,
+                    v => {
+                        return Err(ClientError::Decode(format!(
+                            "Invalid enum base type value {:?}",
+                            v
+                        )))
+                    }
+                };
+                let name = values
+                    .iter()
+                    .find(|e| e.value == value)
+                    .map(|e| e.name.clone())
+                    .unwrap_or_default();
+                PlcValue::Enum { name, value }
+            }
+            DataTypeKind::Alias(target) => PlcValue::decode(target, data)?,
+            DataTypeKind::Pointer(_) | DataTypeKind::Reference(_) => match data.len() {
+                4 => PlcValue::Pointer(u32::decode(data)? as u64),
+                _ => PlcValue::Pointer(u64::decode(data)?),
+            },
+        };
+        Ok(value)
+    }
+
+    /// Encode the value into the PLC representation of data_type. `buf` has to be data_type.size bytes long.
+    pub fn encode(&self, data_type: &DataType, buf: &mut [u8]) -> ClientResult<()> {
+        check_len(buf, data_type.size as usize)?;
+        let buf = &mut buf[..data_type.size as usize];
+        match (&data_type.kind, self) {
+            (DataTypeKind::Primitive(p), v) => encode_primitive(*p, v, buf),
+            (DataTypeKind::String(_), PlcValue::String(s)) => {
+                let bytes = s.as_bytes();
+                if bytes.len() >= buf.len() {
+                    return Err(ClientError::Decode(format!(
+                        "String does not fit into {}",
+                        data_type.name
+                    )));
+                }
+                buf.fill(0);
+                buf[..bytes.len()].copy_from_slice(bytes);
+                Ok(())
+            }
+            (DataTypeKind::WString(_), PlcValue::String(s)) => {
+                let units: Vec<u16> = s.encode_utf16().collect();
+                if units.len() * 2 >= buf.len() {
+                    return Err(ClientError::Decode(format!(
+                        "String does not fit into {}",
+                        data_type.name
+                    )));
+                }
+                buf.fill(0);
+                for (unit, chunk) in units.iter().zip(buf.chunks_exact_mut(2)) {
+                    chunk.copy_from_slice(&unit.to_le_bytes());
+                }
+                Ok(())
+            }
+            (DataTypeKind::Struct(members), PlcValue::Struct(values)) => {
+                buf.fill(0);
+                for member in members {
+                    let value = values
+                        .iter()
+                        .find(|(name, _)| name.eq_ignore_ascii_case(&member.name))
+                        .map(|(_, v)| v)
+                        .ok_or_else(|| {
+                            ClientError::Decode(format!(
+                                "Member {} missing in value for {}",
+                                member.name, data_type.name
+                            ))
+                        })?;
+                    let offset = member.offset as usize;
+                    let end = offset + member.data_type.size as usize;
+                    if end > buf.len() {
+                        return Err(ClientError::Decode(format!(
+                            "Member {} exceeds {}",
+                            member.name, data_type.name
+                        )));
+                    }
+                    value.encode(&member.data_type, &mut buf[offset..end])?;
+                }
+                Ok(())
+            }
+            (
+                DataTypeKind::Array {
+                    dimensions,
+                    element,
+                },
+                PlcValue::Array(values),
+            ) => encode_array(dimensions, element, values, buf),
+            (DataTypeKind::Enum { base, values }, v) => {
+                let value = match v {
+                    PlcValue::Enum { name, value } if name.is_empty() => *value,
+                    PlcValue::Enum { name, .. } => values
+                        .iter()
+                        .find(|e| e.name.eq_ignore_ascii_case(name))
+                        .map(|e| e.value)
+                        .ok_or_else(|| {
+                            ClientError::Decode(format!(
+                                "{} is not a value of {}",
+                                name, data_type.name
+                            ))
+                        })?,
+                    PlcValue::Int(v) => *v,
+                    PlcValue::UInt(v) => *v as i64,
+                    v => {
+                        return Err(ClientError::Decode(format!(
+                            "Invalid value {:?} for enum {}",
+                            v, data_type.name
+                        )))
+                    }
+                };
+                if is_signed(base) {
+                    PlcValue::Int(value).encode(base, buf)
+                } else {
+                    PlcValue::UInt(value as u64).encode(base, buf)
+                }
+            }
+            (DataTypeKind::Alias(target), v) => v.encode(target, buf),
+            (DataTypeKind::Pointer(_), PlcValue::Pointer(p))
+            | (DataTypeKind::Reference(_), PlcValue::Pointer(p)) => match buf.len() {
+                4 => u32::try_from(*p)?.encode(buf),
+                _ => p.encode(buf),
+            },
+            (_, v) => Err(ClientError::Decode(format!(
+                "Invalid value {:?} for {}",
+                v, data_type.name
+            ))),
+        }
+    }
+}
+
+fn is_signed(data_type: &DataType) -> bool {
+    match &data_type.kind {
+        DataTypeKind::Primitive(p) => matches!(
+            p,
+            PrimitiveType::SInt | PrimitiveType::Int | PrimitiveType::DInt | PrimitiveType::LInt
+        ),
+        DataTypeKind::Alias(target) => is_signed(target),
+        _ => false,
+    }
+}
+
+fn decode_primitive(p: PrimitiveType, data: &[u8]) -> ClientResult<PlcValue> {
+    let value = match p {
+        PrimitiveType::Bool => PlcValue::Bool(bool::decode(data)?),
+        PrimitiveType::SInt => PlcValue::Int(i8::decode(data)? as i64),
+        PrimitiveType::Int => PlcValue::Int(i16::decode(data)? as i64),
+        PrimitiveType::DInt => PlcValue::Int(i32::decode(data)? as i64),
+        PrimitiveType::LInt => PlcValue::Int(i64::decode(data)?),
+        PrimitiveType::Byte | PrimitiveType::USInt => PlcValue::UInt(u8::decode(data)? as u64),
+        PrimitiveType::Word | PrimitiveType::UInt => PlcValue::UInt(u16::decode(data)? as u64),
+        PrimitiveType::DWord | PrimitiveType::UDInt => PlcValue::UInt(u32::decode(data)? as u64),
+        PrimitiveType::LWord | PrimitiveType::ULInt => PlcValue::UInt(u64::decode(data)?),
+        PrimitiveType::Real => PlcValue::Real(f32::decode(data)? as f64),
+        PrimitiveType::LReal => PlcValue::Real(f64::decode(data)?),
+        PrimitiveType::Time | PrimitiveType::TimeOfDay => {
+            PlcValue::Time(Duration::from_millis(u32::decode(data)? as u64))
+        }
+        PrimitiveType::LTime => PlcValue::Time(Duration::from_nanos(u64::decode(data)?)),
+        PrimitiveType::Date | PrimitiveType::DateAndTime => {
+            PlcValue::DateTime(UNIX_EPOCH + Duration::from_secs(u32::decode(data)? as u64))
+        }
+    };
+    Ok(value)
+}
+
+fn encode_primitive(p: PrimitiveType, value: &PlcValue, buf: &mut [u8]) -> ClientResult<()> {
+    match (p, value) {
+        (PrimitiveType::Bool, PlcValue::Bool(v)) => v.encode(buf),
+        (PrimitiveType::SInt, PlcValue::Int(v)) => i8::try_from(*v)?.encode(buf),
+        (PrimitiveType::Int, PlcValue::Int(v)) => i16::try_from(*v)?.encode(buf),
+        (PrimitiveType::DInt, PlcValue::Int(v)) => i32::try_from(*v)?.encode(buf),
+        (PrimitiveType::LInt, PlcValue::Int(v)) => v.encode(buf),
+        (PrimitiveType::Byte | PrimitiveType::USInt, PlcValue::UInt(v)) => {
+            u8::try_from(*v)?.encode(buf)
+        }
+        (PrimitiveType::Word | PrimitiveType::UInt, PlcValue::UInt(v)) => {
+            u16::try_from(*v)?.encode(buf)
+        }
+        (PrimitiveType::DWord | PrimitiveType::UDInt, PlcValue::UInt(v)) => {
+            u32::try_from(*v)?.encode(buf)
+        }
+        (PrimitiveType::LWord | PrimitiveType::ULInt, PlcValue::UInt(v)) => v.encode(buf),
+        (PrimitiveType::Real, PlcValue::Real(v)) => (*v as f32).encode(buf),
+        (PrimitiveType::LReal, PlcValue::Real(v)) => v.encode(buf),
+        (PrimitiveType::Time | PrimitiveType::TimeOfDay, PlcValue::Time(d)) => {
+            u32::try_from(d.as_millis())?.encode(buf)
+        }
+        (PrimitiveType::LTime, PlcValue::Time(d)) => u64::try_from(d.as_nanos())?.encode(buf),
+        (PrimitiveType::Date | PrimitiveType::DateAndTime, PlcValue::DateTime(t)) => {
+            DateAndTime::try_from(*t)?.encode(buf)
+        }
+        (p, v) => Err(ClientError::Decode(format!(
+            "Invalid value {:?} for {}",
+            v,
+            p.name()
+        ))),
+    }
+}
+
+fn decode_array(
+    dimensions: &[ArrayDimension],
+    element: &DataType,
+    data: &[u8],
+) -> ClientResult<PlcValue> {
+    let (dimension, inner) = match dimensions.split_first() {
+        Some(d) => d,
+        None => return PlcValue::decode(element, data),
+    };
+    let stride =
+        inner.iter().map(|d| d.elements as usize).product::<usize>() * element.size as usize;
+    let mut values = Vec::with_capacity(dimension.elements as usize);
+    for n in 0..dimension.elements as usize {
+        let start = n * stride;
+        let chunk = data.get(start..start + stride).ok_or_else(|| {
+            ClientError::Decode(format!(
+                "Array data too short for {} elements",
+                dimension.elements
+            ))
+        })?;
+        values.push(decode_array(inner, element, chunk)?);
+    }
+    Ok(PlcValue::Array(values))
+}
+
+fn encode_array(
+    dimensions: &[ArrayDimension],
+    element: &DataType,
+    values: &[PlcValue],
+    buf: &mut [u8],
+) -> ClientResult<()> {
+    let (dimension, inner) = match dimensions.split_first() {
+        Some(d) => d,
+        None => return Err(ClientError::Decode("Array has no dimensions".to_string())),
+    };
+    if values.len() != dimension.elements as usize {
+        return Err(ClientError::Decode(format!(
+            "Expected {} array elements, got {}",
+            dimension.elements,
+            values.len()
+        )));
+    }
+    let stride =
+        inner.iter().map(|d| d.elements as usize).product::<usize>() * element.size as usize;
+    for (n, value) in values.iter().enumerate() {
+        let start = n * stride;
+        let chunk = buf.get_mut(start..start + stride).ok_or_else(|| {
+            ClientError::Decode(format!(
+                "Array buffer too short for {} elements",
+                dimension.elements
+            ))
+        })?;
+        match (inner.is_empty(), value) {
+            (true, v) => v.encode(element, chunk)?,
+            (false, PlcValue::Array(v)) => encode_array(inner, element, v, chunk)?,
+            (false, v) => {
+                return Err(ClientError::Decode(format!(
+                    "Expected nested array, got {:?}",
+                    v
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_type::{EnumValue, StructMember};
+
+    fn data_type(name: &str, size: u32, kind: DataTypeKind) -> DataType {
+        DataType {
+            name: name.to_string(),
+            size,
+            comment: String::new(),
+            kind,
+        }
+    }
+
+    fn primitive(p: PrimitiveType) -> DataType {
+        data_type(p.name(), p.size(), DataTypeKind::Primitive(p))
+    }
+
+    fn member(name: &str, offset: u32, data_type: DataType) -> StructMember {
+        StructMember {
+            name: name.to_string(),
+            offset,
+            data_type,
+        }
+    }
+
+    /// Encode value, compare with the PLC representation and decode it again
+    fn round_trip(data_type: &DataType, value: &PlcValue, bytes: &[u8]) {
+        let mut buf = vec![0xFF; data_type.size as usize];
+        value.encode(data_type, &mut buf).unwrap();
+        assert_eq!(buf, bytes);
+        assert_eq!(&PlcValue::decode(data_type, bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn struct_members_at_their_offsets() {
+        //ST_Sample: flag BOOL @0, counter INT @2, value LREAL @8
+        let st_sample = data_type(
+            "ST_Sample",
+            16,
+            DataTypeKind::Struct(vec![
+                member("flag", 0, primitive(PrimitiveType::Bool)),
+                member("counter", 2, primitive(PrimitiveType::Int)),
+                member("value", 8, primitive(PrimitiveType::LReal)),
+            ]),
+        );
+        let value = PlcValue::Struct(vec![
+            ("flag".to_string(), PlcValue::Bool(true)),
+            ("counter".to_string(), PlcValue::Int(-2)),
+            ("value".to_string(), PlcValue::Real(1.5)),
+        ]);
+        let chunks: &[&[u8]] = &[
+            &[1, 0],                //flag, padding
+            &(-2i16).to_le_bytes(), //counter
+            &[0; 4],                //padding
+            &1.5f64.to_le_bytes(),  //value
+        ];
+        round_trip(&st_sample, &value, &chunks.concat());
+    }
+
+    #[test]
+    fn nested_arrays() {
+        //ARRAY[1..2, 0..2] OF INT
+        let array = data_type(
+            "ARRAY [1..2,0..2] OF INT",
+            12,
+            DataTypeKind::Array {
+                dimensions: vec![
+                    ArrayDimension {
+                        lower_bound: 1,
+                        elements: 2,
+                    },
+                    ArrayDimension {
+                        lower_bound: 0,
+                        elements: 3,
+                    },
+                ],
+                element: Box::new(primitive(PrimitiveType::Int)),
+            },
+        );
+        let row = |values: [i64; 3]| PlcValue::Array(values.map(PlcValue::Int).to_vec());
+        let value = PlcValue::Array(vec![row([1, 2, 3]), row([4, 5, 6])]);
+        round_trip(&array, &value, &[1, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6, 0]);
+
+        let flat = PlcValue::Array((1..=2).map(PlcValue::Int).collect());
+        assert!(flat.encode(&array, &mut [0; 12]).is_err());
+        assert!(PlcValue::decode(&array, &[0; 11]).is_err());
+    }
+
+    #[test]
+    fn enum_by_name_or_value() {
+        let e_state = data_type(
+            "E_State",
+            2,
+            DataTypeKind::Enum {
+                base: Box::new(primitive(PrimitiveType::Int)),
+                values: vec![
+                    EnumValue {
+                        name: "Idle".to_string(),
+                        value: 0,
+                    },
+                    EnumValue {
+                        name: "Error".to_string(),
+                        value: -1,
+                    },
+                ],
+            },
+        );
+        let error = PlcValue::Enum {
+            name: "Error".to_string(),
+            value: -1,
+        };
+        round_trip(&e_state, &error, &[0xFF, 0xFF]);
+
+        //Encode by name (case insensitive) or by value
+        let mut buf = [0; 2];
+        PlcValue::Enum {
+            name: "error".to_string(),
+            value: 0,
+        }
+        .encode(&e_state, &mut buf)
+        .unwrap();
+        assert_eq!(buf, [0xFF, 0xFF]);
+        PlcValue::Int(-1).encode(&e_state, &mut buf).unwrap();
+        assert_eq!(buf, [0xFF, 0xFF]);
+
+        //Values which are not declared decode without name
+        let undeclared = PlcValue::Enum {
+            name: String::new(),
+            value: 7,
+        };
+        round_trip(&e_state, &undeclared, &[7, 0]);
+        let unknown = PlcValue::Enum {
+            name: "Busy".to_string(),
+            value: 0,
+        };
+        assert!(unknown.encode(&e_state, &mut buf).is_err());
+    }
+
+    #[test]
+    fn wstring() {
+        //WSTRING(3)
+        let wstring = data_type("WSTRING(3)", 8, DataTypeKind::WString(3));
+        let value = PlcValue::String("aä".to_string());
+        round_trip(&wstring, &value, &[b'a', 0, 0xE4, 0, 0, 0, 0, 0]);
+        let too_long = PlcValue::String("abcd".to_string());
+        assert!(too_long.encode(&wstring, &mut [0; 8]).is_err());
+    }
+
+    #[test]
+    fn pointer_sizes() {
+        let pointer_64 = data_type(
+            "POINTER TO INT",
+            8,
+            DataTypeKind::Pointer("INT".to_string()),
+        );
+        let address = PlcValue::Pointer(0x1122_3344_5566_7788);
+        round_trip(
+            &pointer_64,
+            &address,
+            &0x1122_3344_5566_7788u64.to_le_bytes(),
+        );
+
+        let pointer_32 = data_type(
+            "POINTER TO INT",
+            4,
+            DataTypeKind::Pointer("INT".to_string()),
+        );
+        round_trip(
+            &pointer_32,
+            &PlcValue::Pointer(0x1122_3344),
+            &[0x44, 0x33, 0x22, 0x11],
+        );
+        //An address beyond 32 bits must not be truncated
+        assert!(matches!(
+            address.encode(&pointer_32, &mut [0; 4]),
+            Err(ClientError::Decode(_))
+        ));
+    }
+}
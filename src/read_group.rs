@@ -0,0 +1,358 @@
+use crate::ads_type::AdsType;
+use crate::client::ClientResult;
+use crate::error::ClientError;
+use crate::request_factory::get_read_request;
+use crate::sumup::{sumup_read_chunks, sumup_response_data};
+use ads_proto::error::AdsError;
+use ads_proto::proto::ams_address::AmsAddress;
+use ads_proto::proto::request::{ReadRequest, Request};
+use ads_proto::proto::response::Response;
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::HashMap;
+
+/// Prepared sumup read of a fixed list of vars. Create it with Client::prepare_read_group and read it with Client::read_group.
+/// The var handles and the encoded sumup requests are kept, the values are read into one buffer which is allocated once.
+#[derive(Debug, Clone)]
+pub struct ReadGroup {
+    vars: Vec<ReadGroupVar>,
+    /// Encoded sumup requests. They are sent again by every read, only the invoke id is updated.
+    frames: Vec<Vec<u8>>,
+    /// Indices of the vars of every sumup request
+    indices: Vec<Vec<usize>>,
+    /// Values of all vars. Every var has a fixed range of its length.
+    data: Vec<u8>,
+    /// Handle generation of the client the requests have been built with
+    handle_generation: Option<u32>,
+    /// Source address the requests have been encoded with
+    source: Option<AmsAddress>,
+}
+
+#[derive(Debug, Clone)]
+struct ReadGroupVar {
+    name: String,
+    offset: usize,
+    length: usize,
+    /// Number of bytes received by the last read
+    received: usize,
+    result: Result<(), AdsError>,
+}
+
+impl ReadGroup {
+    pub(crate) fn new(var_list: &[(&str, u32)]) -> Self {
+        let mut offset = 0;
+        let vars: Vec<ReadGroupVar> = var_list
+            .iter()
+            .map(|(name, length)| {
+                let var = ReadGroupVar {
+                    name: name.to_string(),
+                    offset,
+                    length: *length as usize,
+                    received: 0,
+                    result: Ok(()),
+                };
+                offset += var.length;
+                var
+            })
+            .collect();
+        ReadGroup {
+            vars,
+            frames: Vec::new(),
+            indices: Vec::new(),
+            data: vec![0; offset],
+            handle_generation: None,
+            source: None,
+        }
+    }
+
+    /// Names of the vars in the order of the group
+    pub fn names(&self) -> Vec<&str> {
+        self.vars.iter().map(|var| var.name.as_str()).collect()
+    }
+
+    /// Number of vars in the group
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    /// Value of the var at index from the last read or the error of the host for this var.
+    /// None if index is out of range.
+    pub fn value(&self, index: usize) -> Option<Result<&[u8], AdsError>> {
+        self.vars.get(index).map(|var| {
+            var.result
+                .clone()
+                .map(|_| &self.data[var.offset..var.offset + var.received])
+        })
+    }
+
+    /// Value of the var with name from the last read. See value.
+    pub fn value_by_name(&self, name: &str) -> Option<Result<&[u8], AdsError>> {
+        let index = self.vars.iter().position(|var| var.name == name)?;
+        self.value(index)
+    }
+
+    /// Decode the value of the var at index from the last read
+    pub fn decode<T: AdsType>(&self, index: usize) -> ClientResult<T> {
+        match self.value(index) {
+            Some(Ok(data)) => T::decode(data),
+            Some(Err(e)) => Err(ClientError::Ads(e)),
+            None => Err(ClientError::Decode(format!(
+                "Read group has no var at index {}",
+                index
+            ))),
+        }
+    }
+
+    /// Name and value of every var from the last read in the order of the group
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Result<&[u8], AdsError>)> {
+        (0..self.vars.len()).filter_map(move |index| {
+            self.value(index)
+                .map(|value| (self.vars[index].name.as_str(), value))
+        })
+    }
+
+    /// Buffer with the values of all vars in the order of the group
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub(crate) fn var_names(&self) -> Vec<String> {
+        self.vars.iter().map(|var| var.name.clone()).collect()
+    }
+
+    /// True if the requests have been built with the current handles and source address of the client
+    pub(crate) fn is_prepared(&self, handle_generation: u32, source: &AmsAddress) -> bool {
+        self.handle_generation == Some(handle_generation) && self.source.as_ref() == Some(source)
+    }
+
+    /// Build and encode the sumup requests from the var handles. Vars without handle keep the error of the host.
+    pub(crate) fn prepare(
+        &mut self,
+        handles: &HashMap<String, Result<u32, AdsError>>,
+        handle_generation: u32,
+        source: &AmsAddress,
+        encode: impl Fn(Request) -> ClientResult<Vec<u8>>,
+    ) -> ClientResult<()> {
+        let mut requests: Vec<ReadRequest> = Vec::new();
+        let mut indices: Vec<usize> = Vec::new();
+        for (index, var) in self.vars.iter_mut().enumerate() {
+            var.received = 0;
+            var.result = match handles.get(&var.name) {
+                Some(Ok(handle)) => {
+                    requests.push(get_read_request(*handle, u32::try_from(var.length)?));
+                    indices.push(index);
+                    Ok(())
+                }
+                Some(Err(e)) => Err(e.clone()),
+                None => {
+                    return Err(ClientError::HandleNotFound {
+                        name: var.name.clone(),
+                    })
+                }
+            };
+        }
+
+        let mut indices = indices.into_iter();
+        self.frames.clear();
+        self.indices.clear();
+        for (request, count) in sumup_read_chunks(&requests)? {
+            self.frames.push(encode(request)?);
+            self.indices.push(indices.by_ref().take(count).collect());
+        }
+        self.handle_generation = Some(handle_generation);
+        self.source = Some(source.clone());
+        Ok(())
+    }
+
+    /// The prepared sumup requests
+    pub(crate) fn frames(&mut self) -> &mut [Vec<u8>] {
+        &mut self.frames
+    }
+
+    /// Copy the values of the sumup responses (result and length of every var followed by the data) into the buffer
+    pub(crate) fn store(&mut self, responses: Vec<Response>) -> ClientResult<()> {
+        for (indices, response) in self.indices.iter().zip(responses) {
+            let data = sumup_response_data(response)?;
+            let mut headers = data.as_slice();
+            let mut values = data
+                .get(indices.len() * 8..)
+                .ok_or_else(|| ClientError::Decode("Sumup read response too short".to_string()))?;
+            for index in indices {
+                let var = &mut self.vars[*index];
+                let result = AdsError::from(headers.read_u32::<LittleEndian>()?);
+                let length = headers.read_u32::<LittleEndian>()? as usize;
+                if values.len() < length {
+                    return Err(ClientError::Decode(format!(
+                        "Sumup read response too short for {}",
+                        var.name
+                    )));
+                }
+                let (value, rest) = values.split_at(length);
+                values = rest;
+                if result == AdsError::ErrNoError {
+                    var.received = length.min(var.length);
+                    self.data[var.offset..var.offset + var.received]
+                        .copy_from_slice(&value[..var.received]);
+                    var.result = Ok(());
+                } else {
+                    var.received = 0;
+                    var.result = Err(result);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sumup::tests::read_write_response;
+    use ads_proto::proto::ams_address::AmsNetId;
+
+    fn source() -> AmsAddress {
+        AmsAddress::new(AmsNetId::from([192, 168, 0, 2, 1, 1]), 30000)
+    }
+
+    fn handles(list: &[(&str, Result<u32, AdsError>)]) -> HashMap<String, Result<u32, AdsError>> {
+        list.iter()
+            .map(|(name, handle)| (name.to_string(), handle.clone()))
+            .collect()
+    }
+
+    /// The write data of the sumup read requests (index group, handle and length of every var)
+    fn encode(request: Request) -> ClientResult<Vec<u8>> {
+        match request {
+            Request::ReadWrite(request) => Ok(request.data),
+            _ => Err(ClientError::Decode("no sumup request".to_string())),
+        }
+    }
+
+    /// Group of MAIN.a (2 bytes), MAIN.missing (4 bytes) and MAIN.b (4 bytes) prepared with generation 1
+    fn group() -> ReadGroup {
+        let mut group = ReadGroup::new(&[("MAIN.a", 2), ("MAIN.missing", 4), ("MAIN.b", 4)]);
+        let handles = handles(&[
+            ("MAIN.a", Ok(0x10)),
+            ("MAIN.missing", Err(AdsError::AdsErrDeviceSymbolNotFound)),
+            ("MAIN.b", Ok(0x11)),
+        ]);
+        group.prepare(&handles, 1, &source(), encode).unwrap();
+        group
+    }
+
+    #[test]
+    fn prepare_requests_only_the_vars_with_handle() {
+        let mut group = group();
+        let chunks: &[&[u8]] = &[
+            &0xF005u32.to_le_bytes(), //index group MAIN.a
+            &0x10u32.to_le_bytes(),   //handle MAIN.a
+            &2u32.to_le_bytes(),      //length MAIN.a
+            &0xF005u32.to_le_bytes(), //index group MAIN.b
+            &0x11u32.to_le_bytes(),   //handle MAIN.b
+            &4u32.to_le_bytes(),      //length MAIN.b
+        ];
+        assert_eq!(group.frames(), &[chunks.concat()]);
+        assert_eq!(
+            group.value(1),
+            Some(Err(AdsError::AdsErrDeviceSymbolNotFound))
+        );
+    }
+
+    #[test]
+    fn store_keeps_the_error_of_every_var() {
+        let mut group = group();
+        let chunks: &[&[u8]] = &[
+            &0u32.to_le_bytes(),     //result MAIN.a
+            &2u32.to_le_bytes(),     //length MAIN.a
+            &0x701u32.to_le_bytes(), //result MAIN.b
+            &0u32.to_le_bytes(),     //length MAIN.b
+            &[1, 2],                 //MAIN.a
+        ];
+        group
+            .store(vec![read_write_response(&chunks.concat())])
+            .unwrap();
+        assert_eq!(group.value(0), Some(Ok(&[1, 2][..])));
+        assert_eq!(
+            group.value_by_name("MAIN.missing"),
+            Some(Err(AdsError::AdsErrDeviceSymbolNotFound))
+        );
+        assert_eq!(
+            group.value(2),
+            Some(Err(AdsError::AdsErrDeviceServiceNotSupported))
+        );
+        assert_eq!(group.decode::<u16>(0).unwrap(), 0x0201);
+        assert!(matches!(group.decode::<u32>(2), Err(ClientError::Ads(_))));
+        assert_eq!(group.value(3), None);
+    }
+
+    #[test]
+    fn store_keeps_a_shorter_value() {
+        let mut group = group();
+        let chunks: &[&[u8]] = &[
+            &0u32.to_le_bytes(), //result MAIN.a
+            &2u32.to_le_bytes(), //length MAIN.a
+            &0u32.to_le_bytes(), //result MAIN.b
+            &2u32.to_le_bytes(), //length MAIN.b, 4 requested
+            &[1, 2],
+            &[3, 4],
+        ];
+        group
+            .store(vec![read_write_response(&chunks.concat())])
+            .unwrap();
+        assert_eq!(group.value(2), Some(Ok(&[3, 4][..])));
+        //A value shorter than the type fails to decode instead of reading stale data
+        assert!(group.decode::<u32>(2).is_err());
+    }
+
+    #[test]
+    fn short_sumup_response_is_an_error() {
+        let mut group = group();
+        //Header of MAIN.b missing
+        let chunks: &[&[u8]] = &[&0u32.to_le_bytes(), &2u32.to_le_bytes()];
+        let result = group.store(vec![read_write_response(&chunks.concat())]);
+        assert!(matches!(result, Err(ClientError::Decode(_))));
+        //Value of MAIN.b missing
+        let chunks: &[&[u8]] = &[
+            &0u32.to_le_bytes(),
+            &2u32.to_le_bytes(),
+            &0u32.to_le_bytes(),
+            &4u32.to_le_bytes(),
+            &[1, 2],
+        ];
+        let result = group.store(vec![read_write_response(&chunks.concat())]);
+        assert!(matches!(result, Err(ClientError::Decode(_))));
+    }
+
+    #[test]
+    fn prepare_again_after_the_handle_generation_changed() {
+        let mut group = group();
+        assert!(group.is_prepared(1, &source()));
+        assert!(!group.is_prepared(2, &source()));
+        assert!(!group.is_prepared(
+            1,
+            &AmsAddress::new(AmsNetId::from([192, 168, 0, 3, 1, 1]), 30000)
+        ));
+
+        //New handles after a reconnect, MAIN.missing exists now
+        let handles = handles(&[
+            ("MAIN.a", Ok(0x20)),
+            ("MAIN.missing", Ok(0x21)),
+            ("MAIN.b", Ok(0x22)),
+        ]);
+        group.prepare(&handles, 2, &source(), encode).unwrap();
+        assert!(group.is_prepared(2, &source()));
+        let frame = &group.frames()[0];
+        assert_eq!(frame.len(), 3 * 12);
+        assert_eq!(&frame[4..8], &0x20u32.to_le_bytes());
+        assert_eq!(&frame[16..20], &0x21u32.to_le_bytes());
+        assert_eq!(&frame[28..32], &0x22u32.to_le_bytes());
+        assert_eq!(group.value(1), Some(Ok(&[][..])));
+
+        //Every var needs a handle or an error
+        let result = group.prepare(&HashMap::new(), 3, &source(), encode);
+        assert!(matches!(result, Err(ClientError::HandleNotFound { .. })));
+    }
+}
@@ -1,105 +1,201 @@
-use crate::client::ClientResult;
+use crate::client::{lock, ClientResult, ReaderHealth, ReaderState};
+use crate::dispatcher::NotificationSink;
+use crate::error::ClientError;
+use crate::reconnect::SessionEvent;
 use ads_proto::error::AdsError;
 use ads_proto::proto::ams_header::AmsHeader;
 use ads_proto::proto::command_id::CommandID;
 use ads_proto::proto::proto_traits::ReadFrom;
 use ads_proto::proto::response::*;
-use anyhow::anyhow;
 use byteorder::{LittleEndian, ReadBytesExt};
 use std::collections::HashMap;
 use std::io::{ErrorKind, Read};
 use std::net::TcpStream;
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 type SenderTable = HashMap<u32, Sender<ClientResult<Response>>>;
-type SenderTableAdsNotification = HashMap<u32, Sender<ClientResult<(AdsNotificationSample, u64)>>>;
+type SenderTableAdsNotification = HashMap<u32, NotificationSink>;
+type RxGeneral = Receiver<(u32, Sender<ClientResult<Response>>)>;
+type RxNotification = Receiver<NotificationUpdate>;
+
+/// Change of the notification senders of the reader thread
+#[derive(Debug)]
+pub(crate) enum NotificationUpdate {
+    /// Forward the samples of the notification handle to the sink
+    Add(u32, NotificationSink),
+    /// The notification has been deleted. Dropping the sink disconnects its receiver.
+    Remove(u32),
+}
 
 //Tcp Header size without response data
 pub const AMS_TCP_HEADER_SIZE: usize = 6;
+//AMS Header size without data
+const AMS_HEADER_SIZE: usize = 32;
+//Position of the invoke id in the AMS header
+pub(crate) const AMS_HEADER_INVOKE_ID: usize = 28;
+/// Largest AMS packet (AMS header + data) accepted from the network.
+/// Far above any response of an AMS router, a larger length means the stream is corrupted.
+pub(crate) const MAX_AMS_PACKET_SIZE: usize = 64 * 1024 * 1024;
+
+/// Next item read from the TCP stream
+enum Frame {
+    /// Read timeout without any data
+    Idle,
+    Packet(AmsHeader),
+    Malformed(MalformedPacket),
+}
+
+/// AMS packet which could not be decoded.
+/// The invoke id is taken from the raw header if the header is complete.
+pub(crate) struct MalformedPacket {
+    pub invoke_id: Option<u32>,
+    pub error: std::io::Error,
+}
+
+impl MalformedPacket {
+    /// Error returned to the caller waiting for the response
+    pub(crate) fn to_error(&self) -> ClientError {
+        ClientError::ProtocolViolation(format!("Malformed AMS packet: {}", self.error))
+    }
+}
+
+/// Sets the reader state to Stopped when the reader thread ends (also on panic)
+struct StoppedGuard(Arc<Mutex<ReaderHealth>>);
+
+impl Drop for StoppedGuard {
+    fn drop(&mut self) {
+        lock(&self.0).state = ReaderState::Stopped;
+    }
+}
 
 pub fn run_reader_thread(
     stream: TcpStream,
-    rx_general: Receiver<(u32, Sender<ClientResult<Response>>)>,
-    rx_device_notification: Receiver<(u32, Sender<ClientResult<(AdsNotificationSample, u64)>>)>,
+    rx_general: RxGeneral,
+    rx_device_notification: RxNotification,
     rx_update_tcp_stream: Receiver<TcpStream>,
+    rx_cancel: Receiver<u32>,
+    tx_connection_lost: Sender<SessionEvent>,
+    health: Arc<Mutex<ReaderHealth>>,
 ) -> ClientResult<bool> {
     let mut stream = stream.try_clone()?;
-    thread::spawn(move || {
-        let mut ams_header;
-        let mut sender_table_general: SenderTable = HashMap::new();
-        let mut sender_table_device_notivication: SenderTableAdsNotification = HashMap::new();
+    *lock(&health) = ReaderHealth {
+        state: ReaderState::Running,
+        ..ReaderHealth::default()
+    };
+    let guard = StoppedGuard(health.clone());
+    thread::Builder::new()
+        .name("ads-reader".to_string())
+        .spawn(move || {
+            let _guard = guard;
+            let mut ams_header;
+            let mut sender_table_general: SenderTable = HashMap::new();
+            let mut sender_table_device_notivication: SenderTableAdsNotification = HashMap::new();
 
-        loop {
-            //read tcp data (blocking)
-            match read(&mut stream) {
-                Ok(h) => {
-                    ams_header = h;                    
-                }
-                Err(e) => {
-                    match e.kind() {
-                        ErrorKind::UnexpectedEof => {
-                            //get the latest mpsc sender.
-                            update_sender_table(&rx_general, &mut sender_table_general);
-                            update_sender_table_device_notification(
-                                &rx_device_notification,
-                                &mut sender_table_device_notivication,
-                            );
-                            notify_connection_down(
-                                &mut sender_table_general,
-                                &mut sender_table_device_notivication,
-                            );
-                            //Update TCP Stream
-                            stream = update_tcp_stream(&rx_update_tcp_stream, stream);                            
-                            continue;
+            loop {
+                //read tcp data (blocking)
+                match read(&mut stream) {
+                    Ok(Frame::Packet(h)) => {
+                        ams_header = h;
+                    }
+                    Ok(Frame::Idle) => {
+                        //Read timeout without any data. Drop the senders of timed out requests.
+                        update_sender_table(&rx_general, &mut sender_table_general);
+                        remove_cancelled_requests(&rx_cancel, &mut sender_table_general);
+                        update_sender_table_device_notification(
+                            &rx_device_notification,
+                            &mut sender_table_device_notivication,
+                        );
+                        stream = update_tcp_stream(&rx_update_tcp_stream, stream);
+                        continue;
+                    }
+                    Ok(Frame::Malformed(packet)) => {
+                        update_sender_table(&rx_general, &mut sender_table_general);
+                        record_error(&health, packet.to_error().to_string(), true);
+                        if let Some(id) = packet.invoke_id {
+                            if let Some(tx) = sender_table_general.remove(&id) {
+                                let _ = tx.send(Err(packet.to_error()));
+                            }
                         }
-                        _ => {
-                            //get the latest mpsc sender.
-                            update_sender_table(&rx_general, &mut sender_table_general);
-                            update_sender_table_device_notification(
-                                &rx_device_notification,
-                                &mut sender_table_device_notivication,
-                            );
-                            notify_connection_down(
-                                &mut sender_table_general,
-                                &mut sender_table_device_notivication,
-                            );
-                            //Update TCP Stream
-                            stream = update_tcp_stream(&rx_update_tcp_stream, stream);                            
-                            continue;
+                        continue;
+                    }
+                    Err(e) => {
+                        //get the latest mpsc sender.
+                        update_sender_table(&rx_general, &mut sender_table_general);
+                        update_sender_table_device_notification(
+                            &rx_device_notification,
+                            &mut sender_table_device_notivication,
+                        );
+                        notify_connection_down(
+                            &mut sender_table_general,
+                            &mut sender_table_device_notivication,
+                        );
+                        //Notification handles are only valid for the lost connection
+                        sender_table_device_notivication.clear();
+                        record_error(&health, e.to_string(), false);
+                        lock(&health).state = ReaderState::ConnectionLost;
+                        let _ = tx_connection_lost.send(SessionEvent::ConnectionLost);
+                        //Wait for a new TCP stream. The thread ends if the client has been dropped.
+                        match rx_update_tcp_stream.recv() {
+                            Ok(s) => stream = s,
+                            Err(_) => return,
                         }
+                        lock(&health).state = ReaderState::Running;
+                        continue;
                     }
                 }
-            }
-            //Update TCP Stream
-            stream = update_tcp_stream(&rx_update_tcp_stream, stream);
-            //get the latest mpsc sender.
-            update_sender_table(&rx_general, &mut sender_table_general);
-            update_sender_table_device_notification(
-                &rx_device_notification,
-                &mut sender_table_device_notivication,
-            );
-
-            //Send data to client
-            match ams_header.ads_error() {
-                AdsError::ErrNoError => {                    
-                    forward_data(
-                        &mut ams_header,
+                //Update TCP Stream
+                stream = update_tcp_stream(&rx_update_tcp_stream, stream);
+                //get the latest mpsc sender.
+                update_sender_table(&rx_general, &mut sender_table_general);
+                remove_cancelled_requests(&rx_cancel, &mut sender_table_general);
+                update_sender_table_device_notification(
+                    &rx_device_notification,
+                    &mut sender_table_device_notivication,
+                );
+
+                //Send data to client
+                match ams_header.ads_error() {
+                    AdsError::ErrNoError => {
+                        if let Err(e) = forward_data(
+                            &mut ams_header,
+                            &mut sender_table_general,
+                            &mut sender_table_device_notivication,
+                        ) {
+                            record_error(&health, e.to_string(), true);
+                        }
+                    }
+                    AdsError::ErrPortNotConnected => notify_connection_down(
                         &mut sender_table_general,
                         &mut sender_table_device_notivication,
-                    );
-                }
-                AdsError::ErrPortNotConnected => notify_connection_down(
-                    &mut sender_table_general,
-                    &mut sender_table_device_notivication,
-                ),
-                _ => continue,
-            };
-        }
-    });
+                    ),
+                    error => {
+                        let invoke_id = ams_header.invoke_id();
+                        forward_response(
+                            &mut sender_table_general,
+                            &invoke_id,
+                            Err(ClientError::Ams {
+                                invoke_id,
+                                error: error.clone(),
+                            }),
+                        );
+                    }
+                };
+            }
+        })?;
     Ok(true)
 }
 
+/// Keep the last error of the reader thread for Client::reader_health
+pub(crate) fn record_error(health: &Mutex<ReaderHealth>, error: String, decode_error: bool) {
+    let mut health = lock(health);
+    if decode_error {
+        health.decode_errors += 1;
+    }
+    health.last_error = Some(error);
+}
+
 fn update_tcp_stream(rx_tcp_stream: &Receiver<TcpStream>, stream: TcpStream) -> TcpStream {
     if let Ok(new_stream) = rx_tcp_stream.try_recv() {
         new_stream
@@ -108,48 +204,143 @@ fn update_tcp_stream(rx_tcp_stream: &Receiver<TcpStream>, stream: TcpStream) ->
     }
 }
 
-fn update_sender_table(
-    rx: &Receiver<(u32, Sender<ClientResult<Response>>)>,
-    sender_table: &mut HashMap<u32, Sender<ClientResult<Response>>>,
-) {
+fn update_sender_table(rx: &RxGeneral, sender_table: &mut SenderTable) {
     while let Ok(s) = rx.try_recv() {
         sender_table.insert(s.0, s.1);
     }
 }
 
+/// Remove the senders of requests which timed out on the client side
+fn remove_cancelled_requests(rx: &Receiver<u32>, sender_table: &mut SenderTable) {
+    while let Ok(id) = rx.try_recv() {
+        sender_table.remove(&id);
+    }
+}
+
 fn update_sender_table_device_notification(
-    rx: &Receiver<(u32, Sender<ClientResult<(AdsNotificationSample, u64)>>)>,
-    sender_table: &mut HashMap<u32, Sender<ClientResult<(AdsNotificationSample, u64)>>>,
+    rx: &RxNotification,
+    sender_table: &mut SenderTableAdsNotification,
 ) {
-    while let Ok(s) = rx.try_recv() {
-        sender_table.insert(s.0, s.1);
+    while let Ok(update) = rx.try_recv() {
+        match update {
+            NotificationUpdate::Add(handle, sink) => {
+                sender_table.insert(handle, sink);
+            }
+            NotificationUpdate::Remove(handle) => {
+                sender_table.remove(&handle);
+            }
+        }
     }
 }
 
-fn read(tcp_stream: &mut TcpStream) -> Result<AmsHeader, std::io::Error> {
+/// Read the next AMS packet.
+/// Returns Frame::Idle if the read timed out before a new packet started.
+fn read(tcp_stream: &mut TcpStream) -> Result<Frame, std::io::Error> {
     //ToDo update when ads-proto v0.1.1
     let mut buf = vec![0; AMS_TCP_HEADER_SIZE]; //reserved + length
-    tcp_stream.read_exact(&mut buf)?;
-    let mut length = &buf[2..6];
-    let length = length.read_u32::<LittleEndian>()?;
-    let mut buf: Vec<u8> = vec![0; length as usize];
-    tcp_stream.read_exact(&mut buf)?;
-    let ams_header = AmsHeader::read_from(&mut buf.as_slice())?;    
-    Ok(ams_header)
+    if !read_exact_or_idle(tcp_stream, &mut buf)? {
+        return Ok(Frame::Idle);
+    }
+    let mut buf: Vec<u8> = vec![0; ams_packet_length(&buf)?];
+    //The header has been received, the packet follows
+    read_exact_retrying(tcp_stream, &mut buf)?;
+    match parse_packet(&buf) {
+        Ok(ams_header) => Ok(Frame::Packet(ams_header)),
+        Err(packet) => Ok(Frame::Malformed(packet)),
+    }
+}
+
+/// Fill buf from the stream.
+/// Returns false if the read timed out before the first byte. Once data has been received timeouts are retried
+/// so a packet is never split.
+fn read_exact_or_idle(tcp_stream: &mut TcpStream, buf: &mut [u8]) -> Result<bool, std::io::Error> {
+    loop {
+        match tcp_stream.read(buf) {
+            Ok(0) if !buf.is_empty() => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                read_exact_retrying(tcp_stream, &mut buf[n..])?;
+                return Ok(true);
+            }
+            Err(e) if is_read_timeout(&e) => return Ok(false),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Fill buf from the stream. Read timeouts are retried until buf is complete.
+fn read_exact_retrying(tcp_stream: &mut TcpStream, buf: &mut [u8]) -> Result<(), std::io::Error> {
+    let mut pos = 0;
+    while pos < buf.len() {
+        match tcp_stream.read(&mut buf[pos..]) {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => pos += n,
+            Err(e) if is_read_timeout(&e) || e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// A read timeout is reported as WouldBlock on unix and TimedOut on windows
+fn is_read_timeout(e: &std::io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
 }
 
+/// Length of the AMS packet following the AMS/TCP header (reserved + length).
+/// Fails with a ProtocolViolation if the length exceeds MAX_AMS_PACKET_SIZE.
+pub(crate) fn ams_packet_length(tcp_header: &[u8]) -> Result<usize, std::io::Error> {
+    let mut length = &tcp_header[2..AMS_TCP_HEADER_SIZE];
+    let length = length.read_u32::<LittleEndian>()? as usize;
+    if length > MAX_AMS_PACKET_SIZE {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            ClientError::ProtocolViolation(format!(
+                "AMS packet length {} exceeds {} bytes",
+                length, MAX_AMS_PACKET_SIZE
+            )),
+        ));
+    }
+    Ok(length)
+}
+
+/// Decode a complete AMS packet (AMS header and data)
+pub(crate) fn parse_packet(packet: &[u8]) -> Result<AmsHeader, MalformedPacket> {
+    AmsHeader::read_from(&mut &packet[..]).map_err(|error| {
+        let invoke_id = if packet.len() >= AMS_HEADER_SIZE {
+            let mut id = &packet[AMS_HEADER_INVOKE_ID..AMS_HEADER_SIZE];
+            id.read_u32::<LittleEndian>().ok()
+        } else {
+            None
+        };
+        MalformedPacket { invoke_id, error }
+    })
+}
+
+/// Extract the response of an AMS packet
+pub(crate) fn extract_response(ams_header: &mut AmsHeader) -> ClientResult<Response> {
+    let response = ams_header.response()?;
+    Ok(response)
+}
+
+/// Extract the notification stream of a DeviceNotification AMS packet
+pub(crate) fn extract_notification_stream(
+    ams_header: &mut AmsHeader,
+) -> ClientResult<AdsNotificationStream> {
+    let notification_stream: AdsNotificationStream = ams_header.response()?.try_into()?;
+    Ok(notification_stream)
+}
+
+/// Forward a response or the samples of a notification to the receivers.
+/// A response which can't be decoded is reported to the waiting caller. Fails if a notification can't be decoded.
 fn forward_data(
     ams_header: &mut AmsHeader,
     sender_table_general: &mut SenderTable,
     sender_table_device_notivication: &mut SenderTableAdsNotification,
-) {
+) -> ClientResult<()> {
     match ams_header.command_id() {
         CommandID::DeviceNotification => {
-            let ads_notification: AdsNotificationStream = ams_header
-                .response()
-                .expect("Not possible to extract response from AmsHeader!")
-                .try_into()
-                .expect("try_into AdsNotificationStream failed!");
+            let ads_notification = extract_notification_stream(ams_header)?;
 
             for header in &ads_notification.ads_stamp_headers {
                 for sample in &header.notification_samples {
@@ -161,42 +352,42 @@ fn forward_data(
                 }
             }
         }
-        _ => {            
-            forward_response(
-                sender_table_general,
-                &ams_header.invoke_id(),
-                ams_header
-                    .response()
-                    .expect("Not possible to extract response from AmsHeader!"),
-            );           
+        _ => {
+            let invoke_id = ams_header.invoke_id();
+            let response = extract_response(ams_header);
+            let decode_error = response.as_ref().err().map(|e| e.to_string());
+            forward_response(sender_table_general, &invoke_id, response);
+            if let Some(e) = decode_error {
+                return Err(ClientError::ProtocolViolation(e));
+            }
         }
     }
+    Ok(())
 }
 
+/// Send a notification sample to its receiver. Receivers which hung up are removed.
 fn forward_ads_notification(
     sender_table: &mut SenderTableAdsNotification,
     id: &u32,
     notification: (AdsNotificationSample, u64),
 ) -> bool {
-    if sender_table.contains_key(id) {
-        if let Some(tx) = sender_table.get(id) {
-            tx.send(Ok(notification)).expect(
-                "Failed to send response from reader thread to parent thread by mpsc channel!",
-            );
+    if let Some(tx) = sender_table.get(id) {
+        if tx.send(Ok(notification)) {
             return true;
         }
+        sender_table.remove(id);
     }
     false
 }
 
-fn forward_response(sender_table: &mut SenderTable, id: &u32, response: Response) -> bool {
-    if sender_table.contains_key(id) {
-        if let Some(tx) = sender_table.remove(id) {
-            tx.send(Ok(response)).expect(
-                "Failed to send response from reader thread to parent thread by mpsc channel!",
-            );
-            return true;
-        }
+fn forward_response(
+    sender_table: &mut SenderTable,
+    id: &u32,
+    response: ClientResult<Response>,
+) -> bool {
+    if let Some(tx) = sender_table.remove(id) {
+        //Fails if the receiver has been dropped after a timeout
+        return tx.send(response).is_ok();
     }
     false
 }
@@ -205,31 +396,79 @@ fn notify_connection_down(
     sender_table: &mut SenderTable,
     sender_table_device_notivication: &mut SenderTableAdsNotification,
 ) {
-    let mut delete_list = Vec::new();
-    for (id, tx) in sender_table.clone() {
-        if tx
-            .send(Err(anyhow!(AdsError::ErrPortNotConnected)))
-            .is_err()
-        {
-            delete_list.push(id);
-        }
+    for (_, tx) in sender_table.drain() {
+        let _ = tx.send(Err(ClientError::NotConnected));
     }
 
-    for id in &delete_list {
-        sender_table.remove(id);
+    sender_table_device_notivication.retain(|_, tx| tx.send(Err(ClientError::NotConnected)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    /// Stream with a short read timeout and the peer writing the chunks with pauses longer than the timeout
+    fn stream_with_slow_peer(chunks: Vec<Vec<u8>>) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut peer, _) = listener.accept().unwrap();
+            for chunk in chunks {
+                thread::sleep(Duration::from_millis(150));
+                peer.write_all(&chunk).unwrap();
+            }
+            thread::sleep(Duration::from_millis(500));
+        });
+        let stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+        stream
     }
 
-    let mut delete_notification_list = Vec::new();
-    for (id, tx) in sender_table_device_notivication.clone() {
-        if tx
-            .send(Err(anyhow!(AdsError::ErrPortNotConnected)))
-            .is_err()
-        {
-            delete_notification_list.push(id);
-        }
+    #[test]
+    fn read_exact_or_idle_is_idle_without_data() {
+        let mut stream = stream_with_slow_peer(vec![]);
+        let mut buf = [0; AMS_TCP_HEADER_SIZE];
+        assert!(!read_exact_or_idle(&mut stream, &mut buf).unwrap());
+    }
+
+    #[test]
+    fn read_exact_or_idle_waits_for_the_rest_after_a_timeout() {
+        let mut stream = stream_with_slow_peer(vec![vec![1, 2], vec![3, 4, 5, 6]]);
+        let mut buf = [0; AMS_TCP_HEADER_SIZE];
+        while !read_exact_or_idle(&mut stream, &mut buf).unwrap() {}
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn read_exact_retrying_waits_for_the_first_byte() {
+        let mut stream = stream_with_slow_peer(vec![vec![1, 2, 3], vec![4]]);
+        let mut buf = [0; 4];
+        read_exact_retrying(&mut stream, &mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn read_fails_if_the_stream_ends_within_a_packet() {
+        let mut stream = stream_with_slow_peer(vec![vec![0, 0, 40, 0, 0, 0], vec![1, 2, 3]]);
+        let result = loop {
+            match read(&mut stream) {
+                Ok(Frame::Idle) => continue,
+                result => break result,
+            }
+        };
+        assert!(result.is_err());
     }
 
-    for id in &delete_notification_list {
-        sender_table_device_notivication.remove(id);
+    #[test]
+    fn ams_packet_length_is_bounded() {
+        assert_eq!(ams_packet_length(&[0, 0, 40, 0, 0, 0]).unwrap(), 40);
+        let error = ams_packet_length(&[0, 0, 0xff, 0xff, 0xff, 0xff]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("Protocol violation"));
     }
 }
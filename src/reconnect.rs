@@ -0,0 +1,209 @@
+use crate::client::{Client, WeakClient};
+use crate::connection_state::ConnectionState;
+use crate::error::ClientError;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::Duration;
+
+/// Reconnect policy of the client. Set it with Client::set_reconnect_policy.
+/// After the connection has been lost the client waits `delay(attempt)` before each reconnect attempt.
+/// The delay starts at initial_delay and is multiplied by multiplier after every failed attempt up to max_delay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// Random part of the delay (0.0 - 1.0). 0.2 shortens every delay by up to 20%.
+    pub jitter: f64,
+    /// Give up after this many attempts. None retries forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before the given attempt (starting at 1)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let max = self.max_delay.as_secs_f64();
+        let delay =
+            (self.initial_delay.as_secs_f64() * self.multiplier.max(1.0).powi(exponent)).min(max);
+        //A NaN jitter or multiplier must not kill the reconnect thread
+        let jitter = if self.jitter.is_nan() {
+            0.0
+        } else {
+            self.jitter.clamp(0.0, 1.0) * random_fraction()
+        };
+        Duration::try_from_secs_f64((delay * (1.0 - jitter)).clamp(0.0, max))
+            .unwrap_or(self.max_delay)
+    }
+
+    /// True if another attempt is allowed after `attempts` failed attempts
+    fn may_retry(&self, attempts: u32) -> bool {
+        !matches!(self.max_attempts, Some(max) if attempts >= max)
+    }
+}
+
+/// Event handled by the reconnect thread
+pub(crate) enum SessionEvent {
+    /// The reader thread lost the connection
+    ConnectionLost,
+    /// The symbol version watch received a new version. Handles are requested again by the reconnect thread
+    /// so the dispatcher thread keeps running the callbacks.
+    SymbolVersionChanged(u8),
+}
+
+/// Random number in 0.0..1.0. Good enough to spread the reconnect attempts of several clients.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Start the thread which reconnects the client after the reader thread lost the connection
+/// and restores handles and notifications after a symbol version change.
+/// The thread ends with the client.
+pub(crate) fn run_reconnect_thread(
+    client: &Client,
+    rx_session: Receiver<SessionEvent>,
+) -> Result<(), ClientError> {
+    let client = client.downgrade();
+    thread::Builder::new()
+        .name("ads-reconnect".to_string())
+        .spawn(move || {
+            while let Ok(event) = rx_session.recv() {
+                if let SessionEvent::SymbolVersionChanged(version) = event {
+                    if !symbol_version_changed(&client, version) {
+                        return;
+                    }
+                    continue;
+                }
+                let policy = match client.upgrade() {
+                    //Ignore signals of connections which are already being replaced
+                    Some(c) if c.connection_state() != ConnectionState::Connected => continue,
+                    Some(c) => match c.reconnect_policy() {
+                        Some(policy) => {
+                            c.set_connection_state(ConnectionState::Reconnecting);
+                            policy
+                        }
+                        None => {
+                            c.forget_session();
+                            c.set_connection_state(ConnectionState::Disconnected);
+                            continue;
+                        }
+                    },
+                    None => return,
+                };
+
+                let mut attempts = 0;
+                let mut state = ConnectionState::Failed;
+                while policy.may_retry(attempts) {
+                    attempts += 1;
+                    thread::sleep(policy.delay(attempts));
+                    match client.upgrade() {
+                        Some(c) => {
+                            if c.restore_connection().is_ok() {
+                                state = ConnectionState::Connected;
+                                break;
+                            }
+                        }
+                        None => return,
+                    }
+                }
+                match client.upgrade() {
+                    Some(c) => c.set_connection_state(state),
+                    None => return,
+                }
+                //Drop signals of connections which have been lost during the attempts
+                while let Ok(event) = rx_session.try_recv() {
+                    if let SessionEvent::SymbolVersionChanged(version) = event {
+                        if !symbol_version_changed(&client, version) {
+                            return;
+                        }
+                    }
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// Restore handles and notifications for the new PLC program. False if the client has been dropped.
+fn symbol_version_changed(client: &WeakClient, version: u8) -> bool {
+    match client.upgrade() {
+        Some(c) => {
+            c.symbol_version_changed(version);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: f64) -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter,
+            max_attempts: Some(3),
+        }
+    }
+
+    #[test]
+    fn delay_grows_exponentially_up_to_max_delay() {
+        let policy = policy(0.0);
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(4), Duration::from_millis(800));
+        assert_eq!(policy.delay(5), Duration::from_secs(1));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_shortens_the_delay() {
+        let policy = policy(0.5);
+        for _ in 0..100 {
+            let delay = policy.delay(2);
+            assert!(delay > Duration::from_millis(100));
+            assert!(delay <= Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn invalid_values_do_not_panic() {
+        assert_eq!(policy(f64::NAN).delay(2), Duration::from_millis(200));
+        let policy = ReconnectPolicy {
+            max_delay: Duration::MAX,
+            multiplier: f64::INFINITY,
+            jitter: 0.0,
+            ..ReconnectPolicy::default()
+        };
+        assert_eq!(policy.delay(3), Duration::MAX);
+    }
+
+    #[test]
+    fn may_retry_until_max_attempts() {
+        let policy = policy(0.0);
+        assert!(policy.may_retry(0));
+        assert!(policy.may_retry(2));
+        assert!(!policy.may_retry(3));
+        let forever = ReconnectPolicy::default();
+        assert!(forever.may_retry(u32::MAX));
+    }
+}
@@ -2,10 +2,30 @@ use ads_proto::ads_services::system_services::{
     ADSIGRP_SUMUP_READEX, ADSIGRP_SUMUP_WRITE, GET_SYMHANDLE_BY_NAME, READ_WRITE_SYMVAL_BY_HANDLE,
     RELEASE_SYMHANDLE, GET_ETHERCAT_MASTER_STATE, GET_PROJECTED_SLAVES, GET_FIXED_ADDRESSES, GET_SLAVE_STATUS
 };
+use crate::symbol::SymbolUploadInfo;
 use ads_proto::proto::ads_state::AdsState;
 use ads_proto::proto::ads_transition_mode::AdsTransMode;
 use ads_proto::proto::request::*;
 
+/// Index group to read the symbol upload info (symbol count, symbol table size, ...)
+pub const ADSIGRP_SYM_UPLOADINFO2: u32 = 0xF00F;
+/// Index group to upload the symbol table
+pub const ADSIGRP_SYM_UPLOAD: u32 = 0xF00B;
+/// Index group to upload the data type dictionary
+pub const ADSIGRP_SYM_DT_UPLOAD: u32 = 0xF00E;
+/// Index group of the sumup read write request. Bundles several ReadWrite requests.
+pub const ADSIGRP_SUMUP_READWRITE: u32 = 0xF082;
+/// Index group of the sumup add device notification request
+pub const ADSIGRP_SUMUP_ADDDEVNOTE: u32 = 0xF085;
+/// Index group of the sumup delete device notification request
+pub const ADSIGRP_SUMUP_DELDEVNOTE: u32 = 0xF086;
+/// Index group of the symbol version (u8). Changes with every new PLC program (download, online change).
+pub const ADSIGRP_SYM_VERSION: u32 = 0xF008;
+/// Index group of the device data (ADS state and device state of the target port)
+pub const ADSIGRP_DEVICE_DATA: u32 = 0xF100;
+/// Index offset of the ADS state (u16) followed by the device state (u16)
+pub const ADSIOFFS_DEVDATA_ADSSTATE: u32 = 0x0000;
+
 pub fn get_var_handle_request(var_name: &str) -> ReadWriteRequest {
     ReadWriteRequest::new(
         GET_SYMHANDLE_BY_NAME.index_group,
@@ -75,6 +95,36 @@ pub fn get_sumup_write_request(
     )
 }
 
+/// index_offset --> number of sub requests
+/// read_len --> result, length and read length of every sub request
+pub fn get_sumup_read_write_request(
+    index_offset: u32,
+    read_len: u32,
+    write_data: Vec<u8>,
+) -> ReadWriteRequest {
+    ReadWriteRequest::new(ADSIGRP_SUMUP_READWRITE, index_offset, read_len, write_data)
+}
+
+/// index_offset --> number of sub requests
+/// read_len --> result and notification handle of every sub request
+pub fn get_sumup_add_device_notification_request(
+    index_offset: u32,
+    read_len: u32,
+    write_data: Vec<u8>,
+) -> ReadWriteRequest {
+    ReadWriteRequest::new(ADSIGRP_SUMUP_ADDDEVNOTE, index_offset, read_len, write_data)
+}
+
+/// index_offset --> number of sub requests
+/// read_len --> result of every sub request
+pub fn get_sumup_delete_device_notification_request(
+    index_offset: u32,
+    read_len: u32,
+    write_data: Vec<u8>,
+) -> ReadWriteRequest {
+    ReadWriteRequest::new(ADSIGRP_SUMUP_DELDEVNOTE, index_offset, read_len, write_data)
+}
+
 pub fn get_add_device_notification(
     handle: u32,
     length: u32,
@@ -92,6 +142,39 @@ pub fn get_add_device_notification(
     )
 }
 
+/// Notification of the data at index group and index offset
+pub fn get_add_device_notification_by_address(
+    index_group: u32,
+    index_offset: u32,
+    length: u32,
+    transmission_mode: AdsTransMode,
+    max_delay: u32,
+    cycle_time: u32,
+) -> AddDeviceNotificationRequest {
+    AddDeviceNotificationRequest::new(
+        index_group,
+        index_offset,
+        length,
+        transmission_mode,
+        max_delay,
+        cycle_time,
+    )
+}
+
+pub fn get_symbol_upload_info_request() -> ReadRequest {
+    ReadRequest::new(ADSIGRP_SYM_UPLOADINFO2, 0, SymbolUploadInfo::LENGTH)
+}
+
+/// len --> symbol_size from the symbol upload info
+pub fn get_symbol_upload_request(len: u32) -> ReadRequest {
+    ReadRequest::new(ADSIGRP_SYM_UPLOAD, 0, len)
+}
+
+/// len --> data_type_size from the symbol upload info
+pub fn get_data_type_upload_request(len: u32) -> ReadRequest {
+    ReadRequest::new(ADSIGRP_SYM_DT_UPLOAD, 0, len)
+}
+
 pub fn get_delete_device_notification(handle: u32) -> DeleteDeviceNotificationRequest {
     DeleteDeviceNotificationRequest::new(handle)
 }
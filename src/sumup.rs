@@ -0,0 +1,860 @@
+use crate::client::{ClientResult, SumupResult};
+use crate::error::ClientError;
+use crate::request_factory::{
+    get_add_device_notification, get_sumup_add_device_notification_request,
+    get_sumup_delete_device_notification_request, get_sumup_read_request,
+    get_sumup_read_write_request, get_sumup_write_request,
+};
+use ads_proto::error::AdsError;
+use ads_proto::proto::ads_transition_mode::AdsTransMode;
+use ads_proto::proto::proto_traits::*;
+use ads_proto::proto::request::{
+    AddDeviceNotificationRequest, ReadRequest, ReadWriteRequest, Request, WriteRequest,
+};
+use ads_proto::proto::response::{ReadResponse, ReadWriteResponse, Response, WriteResponse};
+use ads_proto::proto::sumup::sumup_request::{SumupReadRequest, SumupWriteRequest};
+use ads_proto::proto::sumup::sumup_response::{SumupReadResponse, SumupWriteResponse};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Result of the host and notification handle of a var of a sumup add device notification,
+/// or the error of the handle request of the var
+pub(crate) type NotificationOutcome = Result<(AdsError, u32), AdsError>;
+
+/// Maximum number of sub requests in one sumup request (limit of TwinCAT)
+pub(crate) const MAX_SUMUP_REQUESTS: usize = 500;
+/// Maximum size of the data of one sumup request and of its response in bytes
+pub(crate) const MAX_SUMUP_BYTES: usize = 64 * 1024;
+
+/// Split sub requests into chunks which fit into single sumup requests.
+/// Takes the request and response size of every sub request and returns the index ranges of the chunks.
+/// A sub request which exceeds MAX_SUMUP_BYTES on its own is sent in a chunk of its own.
+pub(crate) fn sumup_chunks(sizes: &[(usize, usize)]) -> Vec<Range<usize>> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut request_bytes = 0;
+    let mut response_bytes = 0;
+    for (index, (request_size, response_size)) in sizes.iter().enumerate() {
+        let full = index - start == MAX_SUMUP_REQUESTS
+            || request_bytes + request_size > MAX_SUMUP_BYTES
+            || response_bytes + response_size > MAX_SUMUP_BYTES;
+        if full && index > start {
+            chunks.push(start..index);
+            start = index;
+            request_bytes = 0;
+            response_bytes = 0;
+        }
+        request_bytes += request_size;
+        response_bytes += response_size;
+    }
+    if start < sizes.len() {
+        chunks.push(start..sizes.len());
+    }
+    chunks
+}
+
+/// Bundle read requests into as many sumup requests as needed.
+/// Returns every sumup request with the number of its sub requests.
+pub(crate) fn sumup_read_chunks(requests: &[ReadRequest]) -> ClientResult<Vec<(Request, usize)>> {
+    //Sub request: index group, index offset, length. Sub response: result, length, data
+    let sizes: Vec<(usize, usize)> = requests
+        .iter()
+        .map(|r| (12, 8 + r.length as usize))
+        .collect();
+    sumup_chunks(&sizes)
+        .into_iter()
+        .map(|chunk| {
+            Ok((
+                sumup_read_request(requests[chunk.clone()].to_vec())?,
+                chunk.len(),
+            ))
+        })
+        .collect()
+}
+
+/// Bundle write requests into as many sumup requests as needed.
+/// Returns every sumup request with the number of its sub requests.
+pub(crate) fn sumup_write_chunks(requests: &[WriteRequest]) -> ClientResult<Vec<(Request, usize)>> {
+    //Sub request: index group, index offset, length, data. Sub response: result
+    let sizes: Vec<(usize, usize)> = requests.iter().map(|r| (12 + r.data.len(), 4)).collect();
+    sumup_chunks(&sizes)
+        .into_iter()
+        .map(|chunk| {
+            Ok((
+                sumup_write_request(requests[chunk.clone()].to_vec())?,
+                chunk.len(),
+            ))
+        })
+        .collect()
+}
+
+/// Bundle read write requests into as many sumup requests as needed.
+/// Returns every sumup request with the number of its sub requests.
+pub(crate) fn sumup_read_write_chunks(
+    requests: &[ReadWriteRequest],
+) -> ClientResult<Vec<(Request, usize)>> {
+    //Sub request: index group, index offset, read length, write length, data. Sub response: result, length, data
+    let sizes: Vec<(usize, usize)> = requests
+        .iter()
+        .map(|r| (16 + r.data.len(), 8 + r.read_length as usize))
+        .collect();
+    sumup_chunks(&sizes)
+        .into_iter()
+        .map(|chunk| {
+            Ok((
+                sumup_read_write_request(&requests[chunk.clone()])?,
+                chunk.len(),
+            ))
+        })
+        .collect()
+}
+
+/// Bundle add device notification requests into as many sumup requests as needed.
+/// Returns every sumup request with the number of its sub requests.
+pub(crate) fn sumup_add_device_notification_chunks(
+    requests: &[AddDeviceNotificationRequest],
+) -> ClientResult<Vec<(Request, usize)>> {
+    //Sub request: index group, index offset, length, mode, max delay, cycle time, reserved. Sub response: result, handle
+    let sizes = vec![(40, 8); requests.len()];
+    sumup_chunks(&sizes)
+        .into_iter()
+        .map(|chunk| {
+            Ok((
+                sumup_add_device_notification_request(&requests[chunk.clone()])?,
+                chunk.len(),
+            ))
+        })
+        .collect()
+}
+
+/// Bundle delete device notification requests into as many sumup requests as needed.
+/// Returns every sumup request with the number of its sub requests.
+pub(crate) fn sumup_delete_device_notification_chunks(
+    handles: &[u32],
+) -> ClientResult<Vec<(Request, usize)>> {
+    //Sub request: notification handle. Sub response: result
+    let sizes = vec![(4, 4); handles.len()];
+    sumup_chunks(&sizes)
+        .into_iter()
+        .map(|chunk| {
+            Ok((
+                sumup_delete_device_notification_request(&handles[chunk.clone()])?,
+                chunk.len(),
+            ))
+        })
+        .collect()
+}
+
+/// Sub requests of a sumup by var name (see Client::sumup_read_by_name). Returns the results of all vars,
+/// the index of every var with handle in the results and its sub request.
+/// Vars without handle already hold the error of the handle request, the others hold `pending`.
+pub(crate) fn sumup_by_name<V, T: Clone, R>(
+    var_list: &[(&str, V)],
+    handles: &HashMap<String, Result<u32, AdsError>>,
+    pending: T,
+    request: impl Fn(u32, &V) -> R,
+) -> ClientResult<(SumupResult<T>, Vec<usize>, Vec<R>)> {
+    let mut results: SumupResult<T> = Vec::with_capacity(var_list.len());
+    let mut requested = Vec::new();
+    let mut requests = Vec::new();
+    for (name, value) in var_list {
+        match handles.get(*name) {
+            Some(Ok(handle)) => {
+                requests.push(request(*handle, value));
+                requested.push(results.len());
+                results.push((name.to_string(), Ok(pending.clone())));
+            }
+            Some(Err(e)) => results.push((name.to_string(), Err(e.clone()))),
+            None => {
+                return Err(ClientError::HandleNotFound {
+                    name: name.to_string(),
+                })
+            }
+        }
+    }
+    Ok((results, requested, requests))
+}
+
+/// Store the data or the error of the host of every requested var of a sumup read by name
+pub(crate) fn store_sumup_read_responses(
+    results: &mut SumupResult<Vec<u8>>,
+    requested: Vec<usize>,
+    responses: Vec<Response>,
+    counts: Vec<usize>,
+) -> ClientResult<()> {
+    let mut read_responses = Vec::with_capacity(requested.len());
+    for (response, count) in responses.into_iter().zip(counts) {
+        read_responses.extend(sumup_read_responses(response, count)?);
+    }
+    for (index, response) in requested.into_iter().zip(read_responses) {
+        results[index].1 = if response.result == AdsError::ErrNoError {
+            Ok(response.data)
+        } else {
+            Err(response.result)
+        };
+    }
+    Ok(())
+}
+
+/// Store the error of the host of every requested var of a sumup write by name
+pub(crate) fn store_sumup_write_responses(
+    results: &mut SumupResult<()>,
+    requested: Vec<usize>,
+    responses: Vec<Response>,
+    counts: Vec<usize>,
+) -> ClientResult<()> {
+    let mut write_responses = Vec::with_capacity(requested.len());
+    for (response, count) in responses.into_iter().zip(counts) {
+        write_responses.extend(sumup_write_responses(response, count)?);
+    }
+    for (index, response) in requested.into_iter().zip(write_responses) {
+        if response.result != AdsError::ErrNoError {
+            results[index].1 = Err(response.result);
+        }
+    }
+    Ok(())
+}
+
+/// The host has to answer every sub request of a sumup request
+fn check_sumup_count(requested: usize, received: usize) -> ClientResult<()> {
+    if requested != received {
+        return Err(ClientError::ProtocolViolation(format!(
+            "Sumup response contains {} of {} sub responses",
+            received, requested
+        )));
+    }
+    Ok(())
+}
+
+/// Bundle read requests into a single sumup request (ADSIGRP_SUMUP_READEX)
+pub(crate) fn sumup_read_request(requests: Vec<ReadRequest>) -> ClientResult<Request> {
+    let mut buf = Vec::new();
+    let sumup_request = SumupReadRequest::new(requests);
+    sumup_request.write_to(&mut buf)?;
+    Ok(Request::ReadWrite(get_sumup_read_request(
+        sumup_request.request_count(),
+        sumup_request.expected_response_len(),
+        buf,
+    )))
+}
+
+/// Split the response of a sumup read with count sub requests into the single read responses
+pub(crate) fn sumup_read_responses(
+    response: Response,
+    count: usize,
+) -> ClientResult<Vec<ReadResponse>> {
+    let read_write_response: ReadWriteResponse = response.try_into()?;
+    if read_write_response.result != AdsError::ErrNoError {
+        return Err(ClientError::Ads(read_write_response.result));
+    }
+    let sumup_read_response =
+        SumupReadResponse::read_from(&mut read_write_response.data.as_slice())?;
+    check_sumup_count(count, sumup_read_response.read_responses.len())?;
+    Ok(sumup_read_response.read_responses)
+}
+
+/// Bundle write requests into a single sumup request (ADSIGRP_SUMUP_WRITE)
+pub(crate) fn sumup_write_request(requests: Vec<WriteRequest>) -> ClientResult<Request> {
+    let mut buf = Vec::new();
+    let sumup_request = SumupWriteRequest::new(requests);
+    sumup_request.write_to(&mut buf)?;
+    Ok(Request::ReadWrite(get_sumup_write_request(
+        sumup_request.request_count(),
+        sumup_request.expected_response_len(),
+        buf,
+    )))
+}
+
+/// Split the response of a sumup write with count sub requests into the single write responses
+pub(crate) fn sumup_write_responses(
+    response: Response,
+    count: usize,
+) -> ClientResult<Vec<WriteResponse>> {
+    let read_write_response: ReadWriteResponse = response.try_into()?;
+    if read_write_response.result != AdsError::ErrNoError {
+        return Err(ClientError::Ads(read_write_response.result));
+    }
+    let sumup_write_response =
+        SumupWriteResponse::read_from(&mut read_write_response.data.as_slice())?;
+    check_sumup_count(count, sumup_write_response.write_responses.len())?;
+    Ok(sumup_write_response.write_responses)
+}
+
+/// Bundle read write requests into a single sumup request (ADSIGRP_SUMUP_READWRITE).
+/// The headers of all sub requests (index group, index offset, read length, write length) are followed by the write data.
+pub(crate) fn sumup_read_write_request(requests: &[ReadWriteRequest]) -> ClientResult<Request> {
+    let mut buf = Vec::new();
+    let mut read_len: u32 = 0;
+    for request in requests {
+        buf.write_u32::<LittleEndian>(request.index_group)?;
+        buf.write_u32::<LittleEndian>(request.index_offset)?;
+        buf.write_u32::<LittleEndian>(request.read_length)?;
+        buf.write_u32::<LittleEndian>(u32::try_from(request.data.len())?)?;
+        //Every sub response starts with result and length
+        read_len += 8 + request.read_length;
+    }
+    for request in requests {
+        buf.extend_from_slice(&request.data);
+    }
+    Ok(Request::ReadWrite(get_sumup_read_write_request(
+        u32::try_from(requests.len())?,
+        read_len,
+        buf,
+    )))
+}
+
+/// Split the response of a sumup read write with count sub requests into the result and the data of every sub request
+pub(crate) fn sumup_read_write_responses(
+    response: Response,
+    count: usize,
+) -> ClientResult<Vec<(AdsError, Vec<u8>)>> {
+    let read_write_response: ReadWriteResponse = response.try_into()?;
+    if read_write_response.result != AdsError::ErrNoError {
+        return Err(ClientError::Ads(read_write_response.result));
+    }
+    let mut data = read_write_response.data.as_slice();
+    if data.len() < count * 8 {
+        return Err(ClientError::Decode(format!(
+            "Sumup read write response too short for {} sub responses ({} bytes)",
+            count,
+            data.len()
+        )));
+    }
+    let mut headers = Vec::with_capacity(count);
+    for _ in 0..count {
+        let result = AdsError::from(data.read_u32::<LittleEndian>()?);
+        let length = data.read_u32::<LittleEndian>()? as usize;
+        headers.push((result, length));
+    }
+    let mut responses = Vec::with_capacity(count);
+    for (result, length) in headers {
+        if data.len() < length {
+            return Err(ClientError::Decode(format!(
+                "Sumup read write response too short ({} of {} bytes)",
+                data.len(),
+                length
+            )));
+        }
+        let (item, rest) = data.split_at(length);
+        responses.push((result, item.to_vec()));
+        data = rest;
+    }
+    Ok(responses)
+}
+
+/// Split the responses of sumup read write requests (see sumup_read_write_chunks) into
+/// the read data or the error of the host of every sub request
+pub(crate) fn sumup_read_write_results(
+    responses: Vec<Response>,
+    counts: Vec<usize>,
+) -> ClientResult<Vec<Result<Vec<u8>, AdsError>>> {
+    let mut results = Vec::with_capacity(counts.iter().sum());
+    for (response, count) in responses.into_iter().zip(counts) {
+        for (error, data) in sumup_read_write_responses(response, count)? {
+            results.push(if error == AdsError::ErrNoError {
+                Ok(data)
+            } else {
+                Err(error)
+            });
+        }
+    }
+    Ok(results)
+}
+
+/// Var handles from the results of handle requests (see request_factory::get_var_handle_request)
+/// sent with sumup_read_write. Returns the handle or the error of the host for every var.
+pub(crate) fn sumup_var_handles(
+    var_list: &[String],
+    results: Vec<Result<Vec<u8>, AdsError>>,
+) -> ClientResult<HashMap<String, Result<u32, AdsError>>> {
+    let mut handles = HashMap::with_capacity(var_list.len());
+    for (var, result) in var_list.iter().zip(results) {
+        let handle = match result {
+            Ok(data) => Ok(data.as_slice().read_u32::<LittleEndian>().map_err(|_| {
+                ClientError::Decode(format!(
+                    "Handle of {} too short ({} bytes)",
+                    var,
+                    data.len()
+                ))
+            })?),
+            Err(error) => Err(error),
+        };
+        handles.insert(var.clone(), handle);
+    }
+    Ok(handles)
+}
+
+/// Add device notification requests of a sumup by var name for the vars with handle
+pub(crate) fn sumup_add_device_notification_requests(
+    var_list: &[(&str, u32)],
+    handles: &HashMap<String, Result<u32, AdsError>>,
+    transmission_mode: AdsTransMode,
+    max_delay: u32,
+    cycle_time: u32,
+) -> Vec<AddDeviceNotificationRequest> {
+    var_list
+        .iter()
+        .filter_map(|(name, length)| match handles.get(*name) {
+            Some(Ok(handle)) => Some(get_add_device_notification(
+                *handle,
+                *length,
+                transmission_mode,
+                max_delay,
+                cycle_time,
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Pair the responses of sumup add device notification requests (see sumup_add_device_notification_requests)
+/// with the vars. Returns the result of the host and the notification handle or the handle error of every var.
+/// If there are less responses than requests the handles of the added notifications are returned as error.
+pub(crate) fn sumup_add_device_notification_outcomes(
+    var_list: &[(&str, u32)],
+    handles: &HashMap<String, Result<u32, AdsError>>,
+    responses: Vec<Response>,
+    counts: Vec<usize>,
+) -> ClientResult<Result<Vec<NotificationOutcome>, Vec<u32>>> {
+    let mut added = Vec::with_capacity(counts.iter().sum());
+    let mut complete = true;
+    for (response, count) in responses.into_iter().zip(counts) {
+        let chunk = sumup_add_device_notification_responses(response, count)?;
+        complete &= chunk.len() == count;
+        added.extend(chunk);
+    }
+    if !complete {
+        //The responses after a short chunk can't be paired with their vars
+        return Ok(Err(added
+            .into_iter()
+            .filter(|(result, _)| *result == AdsError::ErrNoError)
+            .map(|(_, handle)| handle)
+            .collect()));
+    }
+
+    //The responses are in the order of the vars with handle
+    let mut added = added.into_iter();
+    let mut outcomes = Vec::with_capacity(var_list.len());
+    for (name, _) in var_list {
+        outcomes.push(match handles.get(*name) {
+            Some(Ok(_)) => match added.next() {
+                Some(response) => Ok(response),
+                None => return Ok(Err(added_notification_handles(outcomes.iter()))),
+            },
+            Some(Err(error)) => Err(error.clone()),
+            None => {
+                return Err(ClientError::HandleNotFound {
+                    name: name.to_string(),
+                })
+            }
+        });
+    }
+    Ok(Ok(outcomes))
+}
+
+/// Handles of the notifications which have been added on the host
+pub(crate) fn added_notification_handles<'a>(
+    outcomes: impl Iterator<Item = &'a NotificationOutcome>,
+) -> Vec<u32> {
+    outcomes
+        .filter_map(|outcome| match outcome {
+            Ok((AdsError::ErrNoError, handle)) => Some(*handle),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Split the responses of sumup delete device notification requests into the result of every handle
+pub(crate) fn sumup_delete_device_notification_results(
+    responses: Vec<Response>,
+    counts: Vec<usize>,
+) -> ClientResult<Vec<AdsError>> {
+    let mut results = Vec::with_capacity(counts.iter().sum());
+    for (response, count) in responses.into_iter().zip(counts) {
+        results.extend(sumup_delete_device_notification_responses(response, count)?);
+    }
+    Ok(results)
+}
+
+/// Bundle add device notification requests into a single sumup request (ADSIGRP_SUMUP_ADDDEVNOTE)
+pub(crate) fn sumup_add_device_notification_request(
+    requests: &[AddDeviceNotificationRequest],
+) -> ClientResult<Request> {
+    let mut buf = Vec::new();
+    for request in requests {
+        request.write_to(&mut buf)?;
+    }
+    Ok(Request::ReadWrite(
+        get_sumup_add_device_notification_request(
+            u32::try_from(requests.len())?,
+            u32::try_from(requests.len() * 8)?,
+            buf,
+        ),
+    ))
+}
+
+/// Split the response of a sumup add device notification with count sub requests
+/// into the result and the notification handle of every sub request. A short response returns the sub responses it contains.
+pub(crate) fn sumup_add_device_notification_responses(
+    response: Response,
+    count: usize,
+) -> ClientResult<Vec<(AdsError, u32)>> {
+    let data = sumup_response_data(response)?;
+    let mut data = data.as_slice();
+    let mut responses = Vec::with_capacity(count);
+    for _ in 0..count.min(data.len() / 8) {
+        let result = AdsError::from(data.read_u32::<LittleEndian>()?);
+        let handle = data.read_u32::<LittleEndian>()?;
+        responses.push((result, handle));
+    }
+    Ok(responses)
+}
+
+/// Bundle delete device notification requests into a single sumup request (ADSIGRP_SUMUP_DELDEVNOTE)
+pub(crate) fn sumup_delete_device_notification_request(handles: &[u32]) -> ClientResult<Request> {
+    let mut buf = Vec::new();
+    for handle in handles {
+        buf.write_u32::<LittleEndian>(*handle)?;
+    }
+    Ok(Request::ReadWrite(
+        get_sumup_delete_device_notification_request(
+            u32::try_from(handles.len())?,
+            u32::try_from(handles.len() * 4)?,
+            buf,
+        ),
+    ))
+}
+
+/// Split the response of a sumup delete device notification with count sub requests into the result of every sub request
+pub(crate) fn sumup_delete_device_notification_responses(
+    response: Response,
+    count: usize,
+) -> ClientResult<Vec<AdsError>> {
+    let data = sumup_response_data(response)?;
+    let mut data = data.as_slice();
+    let mut responses = Vec::with_capacity(count);
+    for _ in 0..count {
+        responses.push(AdsError::from(data.read_u32::<LittleEndian>()?));
+    }
+    Ok(responses)
+}
+
+/// Data of the ReadWrite response which carries a sumup response
+pub(crate) fn sumup_response_data(response: Response) -> ClientResult<Vec<u8>> {
+    let read_write_response: ReadWriteResponse = response.try_into()?;
+    if read_write_response.result != AdsError::ErrNoError {
+        return Err(ClientError::Ads(read_write_response.result));
+    }
+    Ok(read_write_response.data)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::reader::{extract_response, parse_packet};
+    use crate::request_factory::get_var_handle_request;
+
+    /// ReadWrite response packet carrying the sumup response data
+    pub(crate) fn read_write_response(data: &[u8]) -> Response {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&0u32.to_le_bytes()); //result
+        payload.extend_from_slice(&(data.len() as u32).to_le_bytes()); //length
+        payload.extend_from_slice(data);
+        let chunks: &[&[u8]] = &[
+            &[192, 168, 0, 1, 1, 1, 0x53, 0x03],   //target
+            &[192, 168, 0, 2, 1, 1, 0x20, 0x03],   //source
+            &9u16.to_le_bytes(),                   //command id ReadWrite
+            &5u16.to_le_bytes(),                   //state flags response
+            &(payload.len() as u32).to_le_bytes(), //length
+            &0u32.to_le_bytes(),                   //error
+            &1u32.to_le_bytes(),                   //invoke id
+            &payload,
+        ];
+        let mut ams_header = parse_packet(&chunks.concat())
+            .map_err(|malformed| malformed.error)
+            .unwrap();
+        extract_response(&mut ams_header).unwrap()
+    }
+
+    #[test]
+    fn chunks_hold_at_most_max_sumup_requests() {
+        assert_eq!(sumup_chunks(&[(4, 4); 500]), vec![0..500]);
+        assert_eq!(sumup_chunks(&[(4, 4); 501]), vec![0..500, 500..501]);
+        assert!(sumup_chunks(&[]).is_empty());
+    }
+
+    #[test]
+    fn chunks_split_at_the_byte_limit() {
+        let half = MAX_SUMUP_BYTES / 2;
+        //Request bytes exactly at the limit fit, one byte more splits
+        assert_eq!(sumup_chunks(&[(half, 4), (half, 4)]), vec![0..2]);
+        assert_eq!(sumup_chunks(&[(half, 4), (half + 1, 4)]), vec![0..1, 1..2]);
+        //Same for the response bytes
+        assert_eq!(sumup_chunks(&[(4, half), (4, half)]), vec![0..2]);
+        assert_eq!(sumup_chunks(&[(4, half), (4, half + 1)]), vec![0..1, 1..2]);
+    }
+
+    #[test]
+    fn oversized_item_gets_a_chunk_of_its_own() {
+        let sizes = [(4, 4), (4, MAX_SUMUP_BYTES + 1), (4, 4)];
+        assert_eq!(sumup_chunks(&sizes), vec![0..1, 1..2, 2..3]);
+        assert_eq!(sumup_chunks(&sizes[1..]), vec![0..1, 1..2]);
+    }
+
+    #[test]
+    fn chunk_helpers_count_the_sub_requests() {
+        let requests = vec![WriteRequest::new(0x4020, 0, vec![0; 2]); 501];
+        let counts: Vec<usize> = sumup_write_chunks(&requests)
+            .unwrap()
+            .into_iter()
+            .map(|(_, count)| count)
+            .collect();
+        assert_eq!(counts, vec![500, 1]);
+    }
+
+    #[test]
+    fn merged_results_stay_in_request_order() {
+        //Two chunks: the first with two sub responses, the second with one
+        let first: &[&[u8]] = &[
+            &0u32.to_le_bytes(),     //result 1
+            &2u32.to_le_bytes(),     //length 1
+            &0x710u32.to_le_bytes(), //result 2
+            &0u32.to_le_bytes(),     //length 2
+            &[1, 2],                 //data 1
+        ];
+        let second: &[&[u8]] = &[
+            &0u32.to_le_bytes(), //result 3
+            &1u32.to_le_bytes(), //length 3
+            &[3],                //data 3
+        ];
+        let responses = vec![
+            read_write_response(&first.concat()),
+            read_write_response(&second.concat()),
+        ];
+        let results = sumup_read_write_results(responses, vec![2, 1]).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                Ok(vec![1, 2]),
+                Err(AdsError::AdsErrDeviceSymbolNotFound),
+                Ok(vec![3])
+            ]
+        );
+    }
+
+    #[test]
+    fn read_write_request_writes_all_headers_then_the_data() {
+        let requests = [
+            get_var_handle_request("MAIN.a"),
+            ReadWriteRequest::new(0x4020, 8, 2, vec![9]),
+        ];
+        let request = match sumup_read_write_request(&requests).unwrap() {
+            Request::ReadWrite(request) => request,
+            _ => panic!("no ReadWrite request"),
+        };
+        assert_eq!(request.index_group, 0xF082);
+        assert_eq!(request.index_offset, 2); //number of sub requests
+        assert_eq!(request.read_length, (8 + 4) + (8 + 2));
+        let chunks: &[&[u8]] = &[
+            &0xF003u32.to_le_bytes(), //index group 1
+            &0u32.to_le_bytes(),      //index offset 1
+            &4u32.to_le_bytes(),      //read length 1
+            &6u32.to_le_bytes(),      //write length 1
+            &0x4020u32.to_le_bytes(), //index group 2
+            &8u32.to_le_bytes(),      //index offset 2
+            &2u32.to_le_bytes(),      //read length 2
+            &1u32.to_le_bytes(),      //write length 2
+            b"MAIN.a",                //write data 1
+            &[9],                     //write data 2
+        ];
+        assert_eq!(request.data, chunks.concat());
+    }
+
+    #[test]
+    fn read_write_responses_split_result_and_length_per_sub_response() {
+        let chunks: &[&[u8]] = &[
+            &0u32.to_le_bytes(),     //result 1
+            &4u32.to_le_bytes(),     //length 1
+            &0x710u32.to_le_bytes(), //result 2
+            &0u32.to_le_bytes(),     //length 2
+            &0u32.to_le_bytes(),     //result 3
+            &2u32.to_le_bytes(),     //length 3
+            &[1, 2, 3, 4],           //data 1
+            &[5, 6],                 //data 3
+        ];
+        let responses =
+            sumup_read_write_responses(read_write_response(&chunks.concat()), 3).unwrap();
+        assert_eq!(
+            responses,
+            vec![
+                (AdsError::ErrNoError, vec![1, 2, 3, 4]),
+                (AdsError::AdsErrDeviceSymbolNotFound, vec![]),
+                (AdsError::ErrNoError, vec![5, 6]),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_handle_is_mapped_to_its_var() {
+        let chunks: &[&[u8]] = &[
+            &0u32.to_le_bytes(),      //result MAIN.a
+            &4u32.to_le_bytes(),      //length MAIN.a
+            &0x710u32.to_le_bytes(),  //result MAIN.missing
+            &0u32.to_le_bytes(),      //length MAIN.missing
+            &0x1234u32.to_le_bytes(), //handle MAIN.a
+        ];
+        let results =
+            sumup_read_write_results(vec![read_write_response(&chunks.concat())], vec![2]).unwrap();
+        let var_list = ["MAIN.a".to_string(), "MAIN.missing".to_string()];
+        let handles = sumup_var_handles(&var_list, results).unwrap();
+        assert_eq!(handles.len(), 2);
+        assert_eq!(handles["MAIN.a"], Ok(0x1234));
+        assert_eq!(
+            handles["MAIN.missing"],
+            Err(AdsError::AdsErrDeviceSymbolNotFound)
+        );
+    }
+
+    #[test]
+    fn truncated_read_write_response_is_an_error() {
+        let header: &[&[u8]] = &[&0u32.to_le_bytes(), &4u32.to_le_bytes()];
+        let header = header.concat();
+        //Header of the second sub response missing
+        let result = sumup_read_write_responses(read_write_response(&header), 2);
+        assert!(matches!(result, Err(ClientError::Decode(_))));
+        //Data shorter than the length in the header
+        let result =
+            sumup_read_write_responses(read_write_response(&[&header[..], &[1, 2]].concat()), 1);
+        assert!(matches!(result, Err(ClientError::Decode(_))));
+        //Handle shorter than 4 bytes
+        let var_list = ["MAIN.a".to_string()];
+        let result = sumup_var_handles(&var_list, vec![Ok(vec![1, 2])]);
+        assert!(matches!(result, Err(ClientError::Decode(_))));
+    }
+
+    fn add_device_notification_response(responses: &[(u32, u32)]) -> Response {
+        let mut data = Vec::new();
+        for (result, handle) in responses {
+            data.extend_from_slice(&result.to_le_bytes());
+            data.extend_from_slice(&handle.to_le_bytes());
+        }
+        read_write_response(&data)
+    }
+
+    fn handles() -> HashMap<String, Result<u32, AdsError>> {
+        HashMap::from([
+            ("MAIN.a".to_string(), Ok(0x10)),
+            (
+                "MAIN.missing".to_string(),
+                Err(AdsError::AdsErrDeviceSymbolNotFound),
+            ),
+            ("MAIN.b".to_string(), Ok(0x11)),
+        ])
+    }
+
+    const VAR_LIST: [(&str, u32); 3] = [("MAIN.a", 2), ("MAIN.missing", 4), ("MAIN.b", 8)];
+
+    #[test]
+    fn add_device_notification_request_bundles_the_sub_requests() {
+        let requests = sumup_add_device_notification_requests(
+            &VAR_LIST,
+            &handles(),
+            AdsTransMode::OnChange,
+            10,
+            5,
+        );
+        let request = match sumup_add_device_notification_request(&requests).unwrap() {
+            Request::ReadWrite(request) => request,
+            _ => panic!("no ReadWrite request"),
+        };
+        assert_eq!(request.index_group, 0xF085);
+        assert_eq!(request.index_offset, 2); //MAIN.missing has no handle
+        assert_eq!(request.read_length, 2 * 8);
+        let chunks: &[&[u8]] = &[
+            &0xF005u32.to_le_bytes(), //index group
+            &0x10u32.to_le_bytes(),   //index offset (handle of MAIN.a)
+            &2u32.to_le_bytes(),      //length
+            &4u32.to_le_bytes(),      //transmission mode OnChange
+            &10u32.to_le_bytes(),     //max delay
+            &5u32.to_le_bytes(),      //cycle time
+            &[0; 16],                 //reserved
+            &0xF005u32.to_le_bytes(),
+            &0x11u32.to_le_bytes(),
+            &8u32.to_le_bytes(),
+            &4u32.to_le_bytes(),
+            &10u32.to_le_bytes(),
+            &5u32.to_le_bytes(),
+            &[0; 16],
+        ];
+        assert_eq!(request.data, chunks.concat());
+    }
+
+    #[test]
+    fn delete_device_notification_request_bundles_the_handles() {
+        let request = match sumup_delete_device_notification_request(&[7, 8, 9]).unwrap() {
+            Request::ReadWrite(request) => request,
+            _ => panic!("no ReadWrite request"),
+        };
+        assert_eq!(request.index_group, 0xF086);
+        assert_eq!(request.index_offset, 3);
+        assert_eq!(request.read_length, 3 * 4);
+        let chunks: &[&[u8]] = &[
+            &7u32.to_le_bytes(),
+            &8u32.to_le_bytes(),
+            &9u32.to_le_bytes(),
+        ];
+        assert_eq!(request.data, chunks.concat());
+
+        let chunks: &[&[u8]] = &[&0u32.to_le_bytes(), &0x701u32.to_le_bytes()];
+        let response = read_write_response(&chunks.concat());
+        let results = sumup_delete_device_notification_results(vec![response], vec![2]).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                AdsError::ErrNoError,
+                AdsError::AdsErrDeviceServiceNotSupported
+            ]
+        );
+    }
+
+    #[test]
+    fn add_device_notification_outcomes_follow_the_var_list() {
+        //One sub response per var with handle, the second one rejected by the host
+        let response = add_device_notification_response(&[(0, 0x20), (0x701, 0)]);
+        let outcomes =
+            sumup_add_device_notification_outcomes(&VAR_LIST, &handles(), vec![response], vec![2])
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            outcomes,
+            vec![
+                Ok((AdsError::ErrNoError, 0x20)),
+                Err(AdsError::AdsErrDeviceSymbolNotFound),
+                Ok((AdsError::AdsErrDeviceServiceNotSupported, 0)),
+            ]
+        );
+        assert_eq!(added_notification_handles(outcomes.iter()), vec![0x20]);
+    }
+
+    #[test]
+    fn short_add_device_notification_response_returns_the_added_handles() {
+        //Response too short: delete what was added
+        let response = add_device_notification_response(&[(0, 0x20)]);
+        let outcomes =
+            sumup_add_device_notification_outcomes(&VAR_LIST, &handles(), vec![response], vec![2])
+                .unwrap();
+        assert_eq!(outcomes, Err(vec![0x20]));
+
+        //A short first chunk also returns the handles added by the following chunks
+        let var_list = [("MAIN.a", 2), ("MAIN.b", 8)];
+        let responses = vec![
+            add_device_notification_response(&[]),
+            add_device_notification_response(&[(0, 0x21)]),
+        ];
+        let outcomes =
+            sumup_add_device_notification_outcomes(&var_list, &handles(), responses, vec![1, 1])
+                .unwrap();
+        assert_eq!(outcomes, Err(vec![0x21]));
+    }
+}
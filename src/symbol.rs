@@ -0,0 +1,312 @@
+use crate::client::ClientResult;
+use crate::error::ClientError;
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::HashMap;
+
+/// Symbol flag. The symbol is persistent.
+pub const ADSSYMBOLFLAG_PERSISTENT: u32 = 0x0001;
+/// Symbol flag. The symbol is a bit value.
+pub const ADSSYMBOLFLAG_BITVALUE: u32 = 0x0002;
+/// Symbol flag. The symbol is a reference.
+pub const ADSSYMBOLFLAG_REFERENCETO: u32 = 0x0004;
+/// Symbol flag. The symbol is read only.
+pub const ADSSYMBOLFLAG_READONLY: u32 = 0x0020;
+
+/// Fixed size part of a symbol entry (entry length, index group, index offset, size, data type, flags, name/type/comment length)
+const SYMBOL_ENTRY_HEADER_SIZE: usize = 30;
+
+/// Symbol upload info as returned by ADSIGRP_SYM_UPLOADINFO2
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolUploadInfo {
+    pub symbol_count: u32,
+    pub symbol_size: u32,
+    pub data_type_count: u32,
+    pub data_type_size: u32,
+    pub max_dyn_symbols: u32,
+    pub used_dyn_symbols: u32,
+}
+
+impl SymbolUploadInfo {
+    /// Byte length of the upload info
+    pub const LENGTH: u32 = 24;
+
+    /// Parse the upload info from the data of a ReadResponse
+    pub fn parse(mut data: &[u8]) -> ClientResult<Self> {
+        Ok(SymbolUploadInfo {
+            symbol_count: data.read_u32::<LittleEndian>()?,
+            symbol_size: data.read_u32::<LittleEndian>()?,
+            data_type_count: data.read_u32::<LittleEndian>()?,
+            data_type_size: data.read_u32::<LittleEndian>()?,
+            max_dyn_symbols: data.read_u32::<LittleEndian>()?,
+            used_dyn_symbols: data.read_u32::<LittleEndian>()?,
+        })
+    }
+}
+
+/// A single PLC symbol as uploaded from the symbol table (ADSIGRP_SYM_UPLOAD)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub index_group: u32,
+    pub index_offset: u32,
+    /// Size in bytes
+    pub size: u32,
+    /// ADS data type id (ADST_*)
+    pub data_type: u32,
+    pub type_name: String,
+    pub flags: u32,
+    pub comment: String,
+}
+
+impl Symbol {
+    /// Parse a single symbol entry. The slice is advanced to the next entry.
+    pub fn parse(data: &mut &[u8]) -> ClientResult<Self> {
+        let entry_length = data.read_u32::<LittleEndian>()? as usize;
+        if entry_length < SYMBOL_ENTRY_HEADER_SIZE || entry_length - 4 > data.len() {
+            return Err(ClientError::Decode(format!(
+                "Invalid symbol entry length {} ({} bytes available)",
+                entry_length,
+                data.len() + 4
+            )));
+        }
+        let (mut entry, rest) = data.split_at(entry_length - 4);
+        *data = rest;
+
+        let index_group = entry.read_u32::<LittleEndian>()?;
+        let index_offset = entry.read_u32::<LittleEndian>()?;
+        let size = entry.read_u32::<LittleEndian>()?;
+        let data_type = entry.read_u32::<LittleEndian>()?;
+        let flags = entry.read_u32::<LittleEndian>()?;
+        let name_length = entry.read_u16::<LittleEndian>()?;
+        let type_length = entry.read_u16::<LittleEndian>()?;
+        let comment_length = entry.read_u16::<LittleEndian>()?;
+
+        Ok(Symbol {
+            name: read_string(&mut entry, name_length)?,
+            index_group,
+            index_offset,
+            size,
+            data_type,
+            type_name: read_string(&mut entry, type_length)?,
+            flags,
+            comment: read_string(&mut entry, comment_length)?,
+        })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.flags & ADSSYMBOLFLAG_READONLY != 0
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.flags & ADSSYMBOLFLAG_PERSISTENT != 0
+    }
+}
+
+/// All symbols uploaded from the target.
+/// Symbol names are looked up case insensitive like TwinCAT does.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    index: HashMap<String, usize>,
+}
+
+impl SymbolTable {
+    pub fn new(symbols: Vec<Symbol>) -> Self {
+        let index = symbols
+            .iter()
+            .enumerate()
+            .map(|(n, s)| (s.name.to_lowercase(), n))
+            .collect();
+        SymbolTable { symbols, index }
+    }
+
+    /// Parse the symbol table from the data of a ReadResponse (ADSIGRP_SYM_UPLOAD)
+    pub fn parse(mut data: &[u8]) -> ClientResult<Self> {
+        let mut symbols = Vec::new();
+        while !data.is_empty() {
+            symbols.push(Symbol::parse(&mut data)?);
+        }
+        Ok(SymbolTable::new(symbols))
+    }
+
+    /// Get a symbol by name
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.index
+            .get(&name.to_lowercase())
+            .map(|n| &self.symbols[*n])
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.index.contains_key(&name.to_lowercase())
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Symbol> {
+        self.symbols.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+impl IntoIterator for SymbolTable {
+    type Item = Symbol;
+    type IntoIter = std::vec::IntoIter<Symbol>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.symbols.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a SymbolTable {
+    type Item = &'a Symbol;
+    type IntoIter = std::slice::Iter<'a, Symbol>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.symbols.iter()
+    }
+}
+
+/// Read a string of the given length followed by a null terminator.
+pub(crate) fn read_string(data: &mut &[u8], len: u16) -> ClientResult<String> {
+    let len = len as usize;
+    if data.len() < len + 1 {
+        return Err(ClientError::Decode(format!(
+            "String length {} exceeds remaining entry data ({} bytes)",
+            len,
+            data.len()
+        )));
+    }
+    if data[len] != 0 {
+        return Err(ClientError::Decode(format!(
+            "String of length {} is not null terminated",
+            len
+        )));
+    }
+    let s = String::from_utf8_lossy(&data[..len]).to_string();
+    *data = &data[len + 1..];
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Symbol upload of MAIN.nCounter and GVL.sName
+    fn fixture() -> Vec<u8> {
+        let chunks: &[&[u8]] = &[
+            //MAIN.nCounter: length 56, index group 0x4040, index offset 0x1F4, size 2, data type 2, flags 0x8
+            &[
+                0x38, 0x00, 0x00, 0x00, 0x40, 0x40, 0x00, 0x00, 0xF4, 0x01, 0x00, 0x00, 0x02, 0x00,
+                0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00,
+                //name, type, comment length
+                0x0D, 0x00, 0x03, 0x00, 0x07, 0x00,
+            ],
+            b"MAIN.nCounter\0",
+            b"INT\0",
+            b"counter\0",
+            //GVL.sName (read only, persistent): length 68, index group 0x4040, index offset 0x200, size 81, data type 30, flags 0x21
+            &[
+                0x44, 0x00, 0x00, 0x00, 0x40, 0x40, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x51, 0x00,
+                0x00, 0x00, 0x1E, 0x00, 0x00, 0x00, 0x21, 0x00, 0x00, 0x00,
+                //name, type, comment length
+                0x09, 0x00, 0x0A, 0x00, 0x00, 0x00,
+            ],
+            b"GVL.sName\0",
+            b"STRING(80)\0",
+            b"\0",
+            //type GUID and other optional data
+            &[
+                0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+                0x0F, 0x10,
+            ],
+        ];
+        chunks.concat()
+    }
+
+    #[test]
+    fn parse_symbols() {
+        let table = SymbolTable::parse(&fixture()).unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.get("main.ncounter").unwrap(),
+            &Symbol {
+                name: "MAIN.nCounter".to_string(),
+                index_group: 0x4040,
+                index_offset: 0x1F4,
+                size: 2,
+                data_type: 2,
+                type_name: "INT".to_string(),
+                flags: 0x8,
+                comment: "counter".to_string(),
+            }
+        );
+        let name = table.get("GVL.sName").unwrap();
+        assert_eq!(name.type_name, "STRING(80)");
+        assert_eq!(name.size, 81);
+        assert!(name.comment.is_empty());
+        assert!(name.is_read_only());
+        assert!(name.is_persistent());
+        assert!(!table.contains("GVL.sOther"));
+    }
+
+    #[test]
+    fn parse_single_symbol_advances_to_next_entry() {
+        let data = fixture();
+        let mut rest = data.as_slice();
+        assert_eq!(Symbol::parse(&mut rest).unwrap().name, "MAIN.nCounter");
+        assert_eq!(rest.len(), data.len() - 56);
+        assert_eq!(Symbol::parse(&mut rest).unwrap().name, "GVL.sName");
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn truncated_buffer() {
+        let data = fixture();
+        for len in [2, 29, 55, data.len() - 1] {
+            assert!(SymbolTable::parse(&data[..len]).is_err(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn invalid_entry() {
+        //Entry length below the 30 byte header
+        let mut data = fixture();
+        data[0] = 29;
+        assert!(SymbolTable::parse(&data).is_err());
+
+        //Name length which overruns the terminator
+        let mut data = fixture();
+        data[24] = 14;
+        assert!(SymbolTable::parse(&data).is_err());
+
+        //String lengths exceeding the entry
+        let mut data = fixture();
+        data[28] = 0xFF;
+        assert!(SymbolTable::parse(&data).is_err());
+    }
+
+    #[test]
+    fn parse_upload_info() {
+        let data: Vec<u8> = [2u32, 124, 5, 1000, 10, 1]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        assert_eq!(
+            SymbolUploadInfo::parse(&data).unwrap(),
+            SymbolUploadInfo {
+                symbol_count: 2,
+                symbol_size: 124,
+                data_type_count: 5,
+                data_type_size: 1000,
+                max_dyn_symbols: 10,
+                used_dyn_symbols: 1,
+            }
+        );
+        assert!(SymbolUploadInfo::parse(&data[..20]).is_err());
+    }
+}
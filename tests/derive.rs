@@ -0,0 +1,157 @@
+//Layout of #[derive(AdsStruct)] structs. Run with: cargo test --features derive
+use rust_ads_client::ads_type::{AdsString, AdsStruct, AdsType};
+
+//ST_Pack8 (default pack_mode)
+#[derive(Debug, Clone, PartialEq, AdsStruct)]
+struct Pack8 {
+    flag: bool,
+    value: f64,
+    counter: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, AdsStruct)]
+#[ads(pack_mode = 4)]
+struct Pack4 {
+    flag: bool,
+    value: f64,
+    counter: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, AdsStruct)]
+#[ads(pack_mode = 2)]
+struct Pack2 {
+    flag: bool,
+    value: f64,
+    counter: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, AdsStruct)]
+#[ads(pack_mode = 1)]
+struct Pack1 {
+    flag: bool,
+    value: f64,
+    counter: u16,
+}
+
+//Nested struct, array and string members
+#[derive(Debug, Clone, PartialEq, AdsStruct)]
+struct Nested {
+    flag: bool,
+    inner: Pack8,
+    words: [u16; 3],
+    name: AdsString<5>,
+}
+
+//Array of structs inside a packed struct
+#[derive(Debug, Clone, PartialEq, AdsStruct)]
+#[ads(pack_mode = 1)]
+struct PackedArray {
+    id: u8,
+    items: [Pack8; 2],
+}
+
+/// Encode the value and check the offsets of flag (true), value (1.5) and counter (0x1234)
+fn check_members(bytes: &[u8], flag: usize, value: usize, counter: usize) {
+    assert_eq!(bytes[flag], 1);
+    assert_eq!(&bytes[value..value + 8], &1.5f64.to_le_bytes());
+    assert_eq!(&bytes[counter..counter + 2], &0x1234u16.to_le_bytes());
+}
+
+fn round_trip<T: AdsType + PartialEq + std::fmt::Debug>(value: &T) -> Vec<u8> {
+    let bytes = value.to_bytes().unwrap();
+    assert_eq!(bytes.len(), T::SIZE);
+    assert_eq!(&T::decode(&bytes).unwrap(), value);
+    bytes
+}
+
+fn pack8() -> Pack8 {
+    Pack8 {
+        flag: true,
+        value: 1.5,
+        counter: 0x1234,
+    }
+}
+
+#[test]
+fn pack_mode_8() {
+    assert_eq!(Pack8::SIZE, 24);
+    assert_eq!(Pack8::ALIGN, 8);
+    let bytes = round_trip(&pack8());
+    check_members(&bytes, 0, 8, 16);
+}
+
+#[test]
+fn pack_mode_4() {
+    assert_eq!(Pack4::SIZE, 16);
+    assert_eq!(Pack4::ALIGN, 4);
+    let bytes = round_trip(&Pack4 {
+        flag: true,
+        value: 1.5,
+        counter: 0x1234,
+    });
+    check_members(&bytes, 0, 4, 12);
+}
+
+#[test]
+fn pack_mode_2() {
+    assert_eq!(Pack2::SIZE, 12);
+    assert_eq!(Pack2::ALIGN, 2);
+    let bytes = round_trip(&Pack2 {
+        flag: true,
+        value: 1.5,
+        counter: 0x1234,
+    });
+    check_members(&bytes, 0, 2, 10);
+}
+
+#[test]
+fn pack_mode_1() {
+    assert_eq!(Pack1::SIZE, 11);
+    assert_eq!(Pack1::ALIGN, 1);
+    let bytes = round_trip(&Pack1 {
+        flag: true,
+        value: 1.5,
+        counter: 0x1234,
+    });
+    check_members(&bytes, 0, 1, 9);
+}
+
+#[test]
+fn nested_struct_array_and_string() {
+    //flag @0, inner @8 (24 bytes), words @32 (6 bytes), name @38 (6 bytes), padded to 48
+    assert_eq!(Nested::SIZE, 48);
+    assert_eq!(Nested::ALIGN, 8);
+    let bytes = round_trip(&Nested {
+        flag: true,
+        inner: pack8(),
+        words: [1, 2, 3],
+        name: AdsString::from("abc"),
+    });
+    assert_eq!(bytes[0], 1);
+    assert!(bytes[1..8].iter().all(|b| *b == 0));
+    check_members(&bytes[8..32], 0, 8, 16);
+    assert_eq!(&bytes[32..38], &[1, 0, 2, 0, 3, 0]);
+    assert_eq!(&bytes[38..44], b"abc\0\0\0");
+    assert!(bytes[44..].iter().all(|b| *b == 0));
+}
+
+#[test]
+fn array_of_structs_in_packed_struct() {
+    //The pack_mode of the outer struct doesn't change the layout of the nested struct
+    assert_eq!(PackedArray::SIZE, 49);
+    assert_eq!(PackedArray::ALIGN, 1);
+    let bytes = round_trip(&PackedArray {
+        id: 7,
+        items: [pack8(), pack8()],
+    });
+    assert_eq!(bytes[0], 7);
+    check_members(&bytes[1..25], 0, 8, 16);
+    check_members(&bytes[25..49], 0, 8, 16);
+}
+
+#[test]
+fn short_buffer() {
+    assert!(Pack8::decode(&[0; 23]).is_err());
+    assert!(Pack1::decode(&[0; 10]).is_err());
+    assert!(pack8().encode(&mut [0; 23]).is_err());
+}